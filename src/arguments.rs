@@ -1,17 +1,152 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(name = "Anno1800ModCreator")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
 #[command(about = "A tool to create mods for Anno 1800")]
 pub(crate) struct Arguments {
+    #[command(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Create the mods of the recipes
+    Generate(GenerateArguments),
+    /// Show where the values of an asset or template come from and what the recipes make of them
+    Inspect(InspectArguments),
+    /// Show why a recipe includes, excludes or forces an asset or template and which ModOps it gets
+    Explain(ExplainArguments),
+    /// Write the values the recipes query for every asset, before and after the mods, to a file
+    Export(ExportArguments),
+}
+
+/// The game data and recipes every command works on.
+#[derive(Args)]
+pub(crate) struct DataArguments {
     #[arg(value_parser = check_if_path_exists)]
     pub(crate) input_path: PathBuf,
 
+    /// TOML file describing the mods to create, the built-in recipes are used if omitted
+    #[arg(long, value_parser = check_if_path_exists)]
+    pub(crate) recipes: Option<PathBuf>,
+
+    /// Number of threads to use, 0 uses one thread per CPU core
+    #[arg(long, default_value_t = 0)]
+    pub(crate) threads: usize,
+
+    /// Directory to keep the parsed game data in, so files that did not change are not parsed
+    /// again
+    #[arg(long)]
+    pub(crate) cache: Option<PathBuf>,
+
+    /// Language of the names shown for GUIDs, read from the game's `texts_<language>.xml`
+    #[arg(long, default_value = "english")]
+    pub(crate) language: String,
+}
+
+#[derive(Args)]
+pub(crate) struct GenerateArguments {
+    #[command(flatten)]
+    pub(crate) data: DataArguments,
+
     #[arg(value_parser = check_if_path_exists)]
     pub(crate) output_path: PathBuf,
+
+    /// Skip files, entries and mods with problems instead of stopping, all problems are reported
+    /// at the end
+    #[arg(long)]
+    pub(crate) keep_going: bool,
+
+    /// How ModOps address assets with a GUID
+    #[arg(long, value_enum, default_value_t = ModOpStyle::XPath)]
+    pub(crate) modop_style: ModOpStyle,
+
+    /// How ModOps for data from DLCs and other mods avoid warnings when those are not installed
+    #[arg(long, value_enum, default_value_t = OptionalSources::Condition)]
+    pub(crate) optional_sources: OptionalSources,
+
+    /// Print the changes and files of every mod instead of writing them, the output path is left
+    /// as it is
+    #[arg(long)]
+    pub(crate) dry_run: bool,
+
+    /// How the dry run prints its report
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text, requires = "dry_run")]
+    pub(crate) report_format: ReportFormat,
+}
+
+#[derive(Args)]
+pub(crate) struct InspectArguments {
+    #[command(flatten)]
+    pub(crate) data: DataArguments,
+
+    /// GUID of an asset or name of a template
+    pub(crate) name: String,
+}
+
+#[derive(Args)]
+pub(crate) struct ExplainArguments {
+    #[command(flatten)]
+    pub(crate) data: DataArguments,
+
+    /// Name of the recipe
+    pub(crate) recipe: String,
+
+    /// GUID of an asset or name of a template
+    pub(crate) name: String,
+}
+
+#[derive(Args)]
+pub(crate) struct ExportArguments {
+    #[command(flatten)]
+    pub(crate) data: DataArguments,
+
+    /// File to write the values to
+    pub(crate) output_file: PathBuf,
+
+    /// Format of the file
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub(crate) format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ModOpStyle {
+    /// An XPath searching the whole document, `//Asset[Values/Standard/GUID = '...']/Values`
+    #[value(name = "xpath")]
+    XPath,
+    /// The mod loader's GUID attribute with a path relative to the asset
+    Guid,
+    /// Like guid, assets with identical changes share their ModOps, `GUID="a,b,c"`
+    GuidBatched,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum OptionalSources {
+    /// Only apply the ModOps if the changed node exists, `Condition="..."`
+    Condition,
+    /// Apply the ModOps but do not warn if nothing matches, `AllowNoMatch="1"`
+    AllowNoMatch,
+    /// Treat them like the base game
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ReportFormat {
+    /// A summary to read
+    Text,
+    /// The same report as JSON, for other tools
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    /// One row per value, for spreadsheets
+    Csv,
+    /// An array of the same rows
+    Json,
 }
 
 fn check_if_path_exists(path: &str) -> Result<PathBuf, String> {
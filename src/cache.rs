@@ -0,0 +1,79 @@
+use crate::{database::Entry, error::Error};
+use std::path::{Path, PathBuf};
+use xxhash_rust::xxh3::Xxh3;
+
+/// Changes whenever the stored entries change their shape, so older records are not read.
+const FORMAT: &str = concat!("entries-1-", env!("CARGO_PKG_VERSION"));
+
+/// Entries created from the game data, stored by the hash of the files they come from, so
+/// unchanged files are not parsed again.
+///
+/// The cache is only an optimization: a record that can not be read is created again and one
+/// that can not be written is left out.
+pub(crate) struct Cache {
+    directory: PathBuf,
+}
+
+/// A record holds the files included by ModOps with their hashes, which are checked before its
+/// entries are used, and the entries.
+type Record = (Vec<(PathBuf, u128)>, Vec<Entry>);
+
+impl Cache {
+    pub(crate) fn open(directory: &Path) -> Result<Cache, Error> {
+        std::fs::create_dir_all(directory).map_err(|source| Error::Io {
+            path: directory.to_path_buf(),
+            source,
+        })?;
+        Ok(Cache {
+            directory: directory.to_path_buf(),
+        })
+    }
+
+    /// The entries stored for the key, `None` if there are none or an included file changed.
+    pub(crate) fn load(&self, key: u128) -> Option<Vec<Entry>> {
+        let bytes = std::fs::read(self.record_path(key)).ok()?;
+        let (included, entries) = postcard::from_bytes::<Record>(&bytes).ok()?;
+        included
+            .iter()
+            .all(|(path, hash)| hash_file(path) == Some(*hash))
+            .then_some(entries)
+    }
+
+    pub(crate) fn store(&self, key: u128, included: &[PathBuf], entries: &[Entry]) {
+        let included = included
+            .iter()
+            .filter_map(|path| Some((path.clone(), hash_file(path)?)))
+            .collect::<Vec<_>>();
+        if let Ok(bytes) = postcard::to_stdvec(&(included, entries)) {
+            // Written next to the record first, so no other run reads a partial record.
+            let path = self.record_path(key);
+            let partial_path = path.with_extension(format!("{}.partial", std::process::id()));
+            if std::fs::write(&partial_path, bytes).is_ok()
+                && std::fs::rename(&partial_path, &path).is_err()
+            {
+                let _ = std::fs::remove_file(&partial_path);
+            }
+        }
+    }
+
+    fn record_path(&self, key: u128) -> PathBuf {
+        self.directory.join(format!("{key:032x}.bin"))
+    }
+}
+
+/// The key of the entries created from the given parts, like the contents of the files and
+/// where they come from.
+pub(crate) fn key<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> u128 {
+    let mut hasher = Xxh3::new();
+    hasher.update(FORMAT.as_bytes());
+    parts.into_iter().for_each(|part| {
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    });
+    hasher.digest128()
+}
+
+fn hash_file(path: &Path) -> Option<u128> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(key([bytes.as_slice()]))
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+/// A path inside the game's data, like `data/config/export/main/asset/assets.xml`.
+///
+/// It is kept as components, so it is written with the separator of the host system on disk
+/// while it is shown with the forward slashes the game uses.
+#[derive(Eq, PartialEq, Hash, Debug, Clone, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) struct DataPath(Vec<String>);
+
+impl DataPath {
+    /// The data path of a file in a layer, without the layer's directory, so
+    /// `<input>/maindata/data/...` becomes `data/...`.
+    pub(crate) fn from_layer_file(layer_path: &Path, path: &Path) -> DataPath {
+        DataPath(
+            path.strip_prefix(layer_path)
+                .unwrap()
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// The data path of a file in an `.rda` archive, which already is the path inside it.
+    pub(crate) fn from_archived_file(name: &str) -> DataPath {
+        DataPath(
+            name.split(['/', '\\'])
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    pub(crate) fn components(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub(crate) fn to_path_buf(&self) -> PathBuf {
+        self.0.iter().collect()
+    }
+}
+
+impl fmt::Display for DataPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join("/"))
+    }
+}
@@ -0,0 +1,798 @@
+use crate::{
+    cache::{self, Cache},
+    data_path::DataPath,
+    element::Element,
+    error::Error,
+    helper::{self, DataFiles},
+    identifier::{Identifier, Kind, ParentIdentifier},
+    layer::{DataFile, Layer},
+    patch,
+    source::Source,
+    texts,
+};
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// The game data of all input files, parsed once and shared by every recipe.
+#[derive(Debug, Default)]
+pub(crate) struct AssetDatabase {
+    /// The DefaultValues first, then the templates, then the assets, each in load order.
+    pub(crate) entries: Vec<Entry>,
+    template_index: HashMap<String, usize>,
+    guid_index: HashMap<String, usize>,
+    /// The name shown in the game for each GUID, in the chosen language.
+    texts: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) identifier: Identifier,
+    pub(crate) node_type: NodeType,
+    pub(crate) parent: ParentIdentifier,
+    pub(crate) xpath: String,
+    pub(crate) origin: Origin,
+    /// The element holding the properties: the DefaultValues node itself, the Properties node
+    /// of a template or the Values node of an asset.
+    pub(crate) properties: Option<Element>,
+}
+
+/// Where an entry comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Origin {
+    /// The source defining the entry, or the input mod whose ModOps added it.
+    pub(crate) source: Source,
+    /// The ModIDs of the input mods whose ModOps added or changed the entry.
+    pub(crate) changed_by: BTreeSet<String>,
+}
+
+impl Origin {
+    fn new(element: &Element, file_source: &Source, added_by: Option<&str>) -> Origin {
+        let mut changed_by = BTreeSet::new();
+        collect_changes(element, &mut changed_by);
+        Origin {
+            source: match added_by {
+                Some(mod_id) => {
+                    changed_by.insert(mod_id.to_string());
+                    Source::Mod(mod_id.to_string())
+                }
+                None => file_source.clone(),
+            },
+            changed_by,
+        }
+    }
+}
+
+fn collect_changes(element: &Element, changed_by: &mut BTreeSet<String>) {
+    changed_by.extend(element.added_by.iter().cloned());
+    changed_by.extend(element.changed_by.iter().cloned());
+    element
+        .children
+        .iter()
+        .for_each(|child| collect_changes(child, changed_by));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum NodeType {
+    DefaultValues,
+    Template,
+    Asset,
+}
+
+impl AssetDatabase {
+    /// Reads all files in parallel, the entries still end up in load order so every run
+    /// produces the same database. A template or asset that a later layer defines again replaces
+    /// the earlier definition.
+    ///
+    /// Files and entries with problems are left out, the problems are returned alongside. With a
+    /// cache, only the files that changed since they were stored are parsed.
+    pub(crate) fn load(
+        layers: &[Layer],
+        data_files: &DataFiles,
+        cache: Option<&Cache>,
+    ) -> (AssetDatabase, Vec<Error>) {
+        let ((default_values, (templates, assets)), (texts, texts_errors)) = rayon::join(
+            || {
+                rayon::join(
+                    || {
+                        load_entries(
+                            layers,
+                            &data_files.properties,
+                            cache,
+                            "DefaultValues",
+                            |path, origin, element, xpath| {
+                                Ok(Some(Entry {
+                                    identifier: create_default_values_identifier(path, xpath),
+                                    node_type: NodeType::DefaultValues,
+                                    parent: ParentIdentifier::None,
+                                    xpath: xpath.to_string(),
+                                    origin: origin.clone(),
+                                    properties: Some(element.clone()),
+                                }))
+                            },
+                        )
+                    },
+                    || {
+                        rayon::join(
+                            || {
+                                load_entries(
+                                    layers,
+                                    &data_files.templates,
+                                    cache,
+                                    "Template",
+                                    |path, origin, element, xpath| {
+                                        let Some(identifier) =
+                                            create_template_identifier(path, element, xpath)?
+                                        else {
+                                            return Ok(None);
+                                        };
+                                        Ok(Some(Entry {
+                                            identifier,
+                                            node_type: NodeType::Template,
+                                            parent: ParentIdentifier::DefaultValues,
+                                            xpath: xpath.to_string(),
+                                            origin: origin.clone(),
+                                            properties: find_properties(
+                                                path,
+                                                element,
+                                                xpath,
+                                                "Properties",
+                                            )?,
+                                        }))
+                                    },
+                                )
+                            },
+                            || {
+                                load_entries(
+                                    layers,
+                                    &data_files.assets,
+                                    cache,
+                                    "Asset",
+                                    |path, origin, element, xpath| {
+                                        Ok(Some(Entry {
+                                            identifier: create_asset_identifier(
+                                                path, element, xpath,
+                                            )?,
+                                            node_type: NodeType::Asset,
+                                            parent: create_asset_parent_identifier(
+                                                path, element, xpath,
+                                            )?,
+                                            xpath: xpath.to_string(),
+                                            origin: origin.clone(),
+                                            properties: find_properties(
+                                                path, element, xpath, "Values",
+                                            )?,
+                                        }))
+                                    },
+                                )
+                            },
+                        )
+                    },
+                )
+            },
+            || texts::load_texts(&data_files.texts),
+        );
+
+        let mut database = AssetDatabase {
+            texts,
+            ..AssetDatabase::default()
+        };
+        let mut errors = texts_errors;
+
+        for result in default_values {
+            match result {
+                Ok(entry) => database.entries.push(entry),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        let mut identifiers = HashMap::new();
+        for result in templates.into_iter().chain(assets) {
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            // A later definition takes the position of the one it replaces.
+            let index = *identifiers
+                .entry(entry.identifier.clone())
+                .or_insert(database.entries.len());
+            match (entry.node_type, &entry.identifier.kind) {
+                (NodeType::Template, _) => {
+                    database
+                        .template_index
+                        .insert(entry.identifier.value.clone(), index);
+                }
+                (NodeType::Asset, Kind::GUID) => {
+                    database
+                        .guid_index
+                        .insert(entry.identifier.value.clone(), index);
+                }
+                _ => (),
+            }
+            match index == database.entries.len() {
+                true => database.entries.push(entry),
+                false => database.entries[index] = entry,
+            }
+        }
+
+        (database, errors)
+    }
+
+    /// The index of the asset with the GUID, or else of the template with the name.
+    pub(crate) fn find(&self, name: &str) -> Option<usize> {
+        self.guid_index
+            .get(name)
+            .or_else(|| self.template_index.get(name))
+            .copied()
+    }
+
+    /// The indices of the assets and the template the entry inherits from, nearest first.
+    pub(crate) fn ancestors(&self, index: usize) -> Vec<usize> {
+        let mut ancestors = Vec::new();
+        let mut parent = &self.entries[index].parent;
+        while let Some(parent_index) = self.parent_index(parent) {
+            // A BaseAssetGUID cycle would never end, the game does not load it either.
+            if parent_index == index || ancestors.contains(&parent_index) {
+                break;
+            }
+            ancestors.push(parent_index);
+            parent = &self.entries[parent_index].parent;
+        }
+        ancestors
+    }
+
+    /// The name of the asset with the GUID, from the texts of the chosen language or else from
+    /// its `Standard/Name`.
+    pub(crate) fn name_of(&self, guid: &str) -> Option<&str> {
+        if let Some(text) = self.texts.get(guid) {
+            return Some(text);
+        }
+        standard_name(&self.entries[*self.guid_index.get(guid)?])
+    }
+
+    /// The names the assets with a GUID can be referred to by, their text and their
+    /// `Standard/Name`, together with the GUID.
+    pub(crate) fn asset_names(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.guid_index.iter().flat_map(|(guid, &index)| {
+            let text = self.texts.get(guid).map(String::as_str);
+            let standard_name = standard_name(&self.entries[index]);
+            text.into_iter()
+                .chain(standard_name.filter(|name| Some(*name) != text))
+                .map(move |name| (name, guid.as_str()))
+        })
+    }
+
+    /// The name of the entry if it is an asset with a GUID, see [`AssetDatabase::name_of`].
+    pub(crate) fn name(&self, entry: &Entry) -> Option<&str> {
+        match (entry.node_type, &entry.identifier.kind) {
+            (NodeType::Asset, Kind::GUID) => self.name_of(&entry.identifier.value),
+            _ => None,
+        }
+    }
+
+    /// The node at the path in the properties of the entry, or else in the nearest of its
+    /// ancestors or the last DefaultValues having it, like the game resolves inherited values.
+    /// The DefaultValues only count if the template of the entry has the first node of the path,
+    /// the others do not belong to its assets.
+    pub(crate) fn effective_node(&self, index: usize, path: &[String]) -> Option<&Element> {
+        let chain = [index]
+            .into_iter()
+            .chain(self.ancestors(index))
+            .map(|index| &self.entries[index])
+            .collect::<Vec<_>>();
+        let has_defaults = match chain
+            .iter()
+            .find(|entry| entry.node_type != NodeType::Asset)
+        {
+            Some(entry) if entry.node_type == NodeType::DefaultValues => true,
+            Some(template) => path.first().is_some_and(|name| {
+                template
+                    .properties
+                    .as_ref()
+                    .is_some_and(|properties| helper::has_direct_child(properties, name))
+            }),
+            None => false,
+        };
+        let default_values_count = match has_defaults {
+            true => self
+                .entries
+                .iter()
+                .position(|entry| entry.node_type != NodeType::DefaultValues)
+                .unwrap_or(self.entries.len()),
+            false => 0,
+        };
+        chain
+            .into_iter()
+            .chain(self.entries[..default_values_count].iter().rev())
+            .find_map(|entry| {
+                path.iter()
+                    .try_fold(entry.properties.as_ref()?, |element, name| {
+                        element.child(name).ok().flatten()
+                    })
+            })
+    }
+
+    /// The template the entry inherits from, directly or through other assets.
+    pub(crate) fn template_of(&self, entry: &Entry) -> Option<&Entry> {
+        self.parent_index(&entry.parent)
+            .into_iter()
+            .flat_map(|parent| [parent].into_iter().chain(self.ancestors(parent)))
+            .map(|index| &self.entries[index])
+            .find(|ancestor| ancestor.node_type == NodeType::Template)
+    }
+
+    /// The index of the entry a parent identifier refers to, later definitions win.
+    pub(crate) fn parent_index(&self, parent: &ParentIdentifier) -> Option<usize> {
+        match parent {
+            ParentIdentifier::Template(name) => self.template_index.get(name).copied(),
+            ParentIdentifier::GUID(guid) => self.guid_index.get(guid).copied(),
+            ParentIdentifier::None | ParentIdentifier::DefaultValues => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl AssetDatabase {
+    /// Loads the properties, templates and assets of the base game and the further files, given
+    /// by their path in the input directory and their content, like `generate` does.
+    pub(crate) fn from_documents(
+        properties: &str,
+        templates: &str,
+        assets: &str,
+        files: &[(&str, &str)],
+    ) -> AssetDatabase {
+        let input = tempfile::tempdir().unwrap();
+        let asset_directory = "maindata/data/config/export/main/asset";
+        [
+            (format!("{asset_directory}/properties.xml"), properties),
+            (format!("{asset_directory}/templates.xml"), templates),
+            (format!("{asset_directory}/assets.xml"), assets),
+        ]
+        .into_iter()
+        .chain(
+            files
+                .iter()
+                .map(|(path, content)| (path.to_string(), *content)),
+        )
+        .for_each(|(path, content)| {
+            let path = input.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        });
+        let (layers, errors) = crate::layer::load_layers(input.path());
+        assert!(errors.is_empty(), "{errors:?}");
+        let (data_files, errors) = helper::get_paths(&layers, "english");
+        assert!(errors.is_empty(), "{errors:?}");
+        let (database, errors) = AssetDatabase::load(&layers, &data_files, None);
+        assert!(errors.is_empty(), "{errors:?}");
+        database
+    }
+}
+
+fn standard_name(entry: &Entry) -> Option<&str> {
+    entry
+        .properties
+        .as_ref()?
+        .child("Standard")
+        .ok()??
+        .child("Name")
+        .ok()??
+        .text
+        .as_deref()
+}
+
+/// Creates the entries for all nodes with the given tag name in the files, in load order.
+///
+/// Files of input mods made of `<ModOps>` are applied onto the files they patch first, so the
+/// entries hold the data the game ends up with. With a cache, the entries of the files patching
+/// the same data path are stored together and only created again when one of them changes.
+fn load_entries<F>(
+    layers: &[Layer],
+    paths: &[DataFile],
+    cache: Option<&Cache>,
+    tag_name: &str,
+    create_entry: F,
+) -> Vec<Result<Entry, Error>>
+where
+    F: Fn(&DataPath, &Origin, &Element, &str) -> Result<Option<Entry>, Error> + Sync,
+{
+    let files = paths
+        .par_iter()
+        .map(|file| read_file(layers, file))
+        .collect::<Vec<_>>();
+
+    // The files patching the same data path, in load order.
+    let mut errors = Vec::new();
+    let mut data_paths: Vec<(DataPath, Vec<InputFile>)> = Vec::new();
+    let mut data_path_indices: HashMap<DataPath, usize> = HashMap::new();
+    for file in files {
+        let file = match file {
+            Ok(file) => file,
+            Err(error) => {
+                errors.push(Err(error));
+                continue;
+            }
+        };
+        let index = *data_path_indices
+            .entry(file.data_path.clone())
+            .or_insert_with(|| {
+                data_paths.push((file.data_path.clone(), Vec::new()));
+                data_paths.len() - 1
+            });
+        data_paths[index].1.push(file);
+    }
+
+    let entries = data_paths
+        .into_par_iter()
+        .map(|(data_path, files)| {
+            let key = cache.map(|cache| (cache, cache_key(tag_name, &data_path, &files)));
+            if let Some(entries) = key.and_then(|(cache, key)| cache.load(key)) {
+                return entries.into_iter().map(Ok).collect();
+            }
+
+            let (included, results) = create_entries(&data_path, files, tag_name, &create_entry);
+            let (entries, errors): (Vec<_>, Vec<_>) = results.into_iter().partition_result();
+            // Problems have to be reported again, so only complete results are stored.
+            if let (Some((cache, key)), true) = (key, errors.is_empty()) {
+                cache.store(key, &included, &entries);
+            }
+            errors
+                .into_iter()
+                .map(Err)
+                .chain(entries.into_iter().map(Ok))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    errors
+        .into_iter()
+        .chain(entries.into_iter().flatten())
+        .collect()
+}
+
+/// Creates the entries of the files patching the same data path, the files included by their
+/// ModOps are returned alongside.
+fn create_entries<F>(
+    data_path: &DataPath,
+    files: Vec<InputFile>,
+    tag_name: &str,
+    create_entry: &F,
+) -> (Vec<PathBuf>, Vec<Result<Entry, Error>>)
+where
+    F: Fn(&DataPath, &Origin, &Element, &str) -> Result<Option<Entry>, Error> + Sync,
+{
+    let parsed_files = files
+        .into_par_iter()
+        .map(|file| {
+            let xml =
+                roxmltree::Document::parse(&file.xml_string).map_err(|source| Error::Xml {
+                    path: file.path.clone(),
+                    source,
+                })?;
+            let content = match xml.root_element().tag_name().name() {
+                "ModOps" => FileContent::ModOps,
+                _ => FileContent::Data(Element::from_node(&xml.root_element())),
+            };
+            Ok((file, content))
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::new();
+    let mut included = Vec::new();
+    let mut sources = Vec::new();
+    let mut roots = Vec::new();
+    for parsed_file in parsed_files {
+        let (file, content) = match parsed_file {
+            Ok(parsed_file) => parsed_file,
+            Err(error) => {
+                results.push(Err(error));
+                continue;
+            }
+        };
+        match content {
+            FileContent::Data(root) => {
+                sources.push(file.source);
+                roots.push(root);
+            }
+            FileContent::ModOps if roots.is_empty() => results.push(Err(Error::ModOp {
+                path: file.path,
+                xpath: "/ModOps[1]".to_string(),
+                message: format!("No game data found for {data_path}"),
+            })),
+            FileContent::ModOps => {
+                let (included_paths, errors) = patch::apply_mod_ops(
+                    &mut roots,
+                    &file.path,
+                    &file.mod_root,
+                    &file.xml_string,
+                    file.mod_id.as_deref(),
+                );
+                included.extend(included_paths);
+                results.extend(errors.into_iter().map(Err));
+            }
+        }
+    }
+
+    for (source, root) in sources.iter().zip(&roots) {
+        let mut nodes = Vec::new();
+        collect_nodes(
+            root,
+            tag_name,
+            &format!("/{}[1]", root.name),
+            None,
+            &mut nodes,
+        );
+        results.extend(
+            nodes
+                .par_iter()
+                .filter_map(|(element, xpath, added_by)| {
+                    let origin = Origin::new(element, source, *added_by);
+                    create_entry(data_path, &origin, element, xpath).transpose()
+                })
+                .collect::<Vec<_>>(),
+        );
+    }
+    (included, results)
+}
+
+/// The cache key of the files patching the same data path, from their contents and where they
+/// come from.
+fn cache_key(tag_name: &str, data_path: &DataPath, files: &[InputFile]) -> u128 {
+    let data_path = data_path.to_string();
+    let origins = files
+        .iter()
+        .map(|file| format!("{:?} {:?}", file.source, file.mod_id))
+        .collect::<Vec<_>>();
+    cache::key(
+        [tag_name.as_bytes(), data_path.as_bytes()]
+            .into_iter()
+            .chain(
+                files
+                    .iter()
+                    .zip(&origins)
+                    .flat_map(|(file, origin)| [origin.as_bytes(), file.xml_string.as_bytes()]),
+            ),
+    )
+}
+
+struct InputFile {
+    path: PathBuf,
+    data_path: DataPath,
+    source: Source,
+    /// The ModID of the layer, `None` for the base game.
+    mod_id: Option<String>,
+    /// The directory of the layer, the files included by ModOps have to be inside of it.
+    mod_root: PathBuf,
+    xml_string: String,
+}
+
+enum FileContent {
+    Data(Element),
+    /// A file made of `<ModOps>`, it is applied onto the data files read before it.
+    ModOps,
+}
+
+fn read_file(layers: &[Layer], file: &DataFile) -> Result<InputFile, Error> {
+    let layer = layers
+        .iter()
+        .find(|layer| file.path.starts_with(&layer.path));
+
+    Ok(InputFile {
+        path: file.path.clone(),
+        data_path: file.data_path.clone(),
+        source: layer
+            .map(|layer| layer.source_of(&file.data_path))
+            .unwrap_or(Source::BaseGame),
+        mod_id: layer.and_then(|layer| layer.mod_id.clone()),
+        mod_root: layer
+            .map(|layer| layer.path.clone())
+            .or_else(|| file.path.parent().map(Path::to_path_buf))
+            .unwrap_or_default(),
+        xml_string: file.read_to_string()?,
+    })
+}
+
+/// Collects the elements with the given name and their XPaths, together with the ModID of the
+/// input mod that added them or one of their ancestors.
+fn collect_nodes<'a>(
+    element: &'a Element,
+    tag_name: &str,
+    xpath: &str,
+    added_by: Option<&'a str>,
+    nodes: &mut Vec<(&'a Element, String, Option<&'a str>)>,
+) {
+    let added_by = element.added_by.as_deref().or(added_by);
+    if element.name == tag_name {
+        nodes.push((element, xpath.to_string(), added_by));
+    }
+
+    let mut counters: HashMap<&str, usize> = HashMap::new();
+    element.children.iter().for_each(|child| {
+        let count = counters.entry(&child.name).or_default();
+        *count += 1;
+        let child_xpath = format!("{xpath}/{}[{count}]", child.name);
+        collect_nodes(child, tag_name, &child_xpath, added_by, nodes);
+    });
+}
+
+/// The only child with the given name, an error if there is more than one.
+fn only_child<'a>(
+    path: &DataPath,
+    element: &'a Element,
+    xpath: &str,
+    name: &str,
+) -> Result<Option<&'a Element>, Error> {
+    element.child(name).map_err(|_| Error::Data {
+        file: path.clone(),
+        xpath: xpath.to_string(),
+        message: format!("More than one {name} node found"),
+    })
+}
+
+/// The text of the only child with the given name, an error if the child has no text.
+fn only_child_text(
+    path: &DataPath,
+    element: &Element,
+    xpath: &str,
+    name: &str,
+) -> Result<Option<String>, Error> {
+    let Some(child) = only_child(path, element, xpath, name)? else {
+        return Ok(None);
+    };
+    match &child.text {
+        Some(text) => Ok(Some(text.clone())),
+        None => Err(Error::Data {
+            file: path.clone(),
+            xpath: xpath.to_string(),
+            message: format!("{name} node has no text"),
+        }),
+    }
+}
+
+fn find_properties(
+    path: &DataPath,
+    element: &Element,
+    xpath: &str,
+    name: &str,
+) -> Result<Option<Element>, Error> {
+    Ok(only_child(path, element, xpath, name)?.cloned())
+}
+
+fn create_asset_parent_identifier(
+    path: &DataPath,
+    element: &Element,
+    xpath: &str,
+) -> Result<ParentIdentifier, Error> {
+    if let Some(template_name) = only_child_text(path, element, xpath, "Template")? {
+        return Ok(ParentIdentifier::Template(template_name));
+    }
+
+    if let Some(base_asset_guid) = only_child_text(path, element, xpath, "BaseAssetGUID")? {
+        return Ok(ParentIdentifier::GUID(base_asset_guid));
+    }
+
+    if let Some(scenario_base_asset_guid) =
+        only_child_text(path, element, xpath, "ScenarioBaseAssetGUID")?
+    {
+        return Ok(ParentIdentifier::GUID(scenario_base_asset_guid));
+    }
+
+    Ok(ParentIdentifier::None)
+}
+
+fn create_asset_identifier(
+    path: &DataPath,
+    element: &Element,
+    xpath: &str,
+) -> Result<Identifier, Error> {
+    let xpath_identifier = || Identifier {
+        file_path: path.clone(),
+        kind: Kind::XPath,
+        value: xpath.to_string(),
+    };
+
+    let Some(values) = only_child(path, element, xpath, "Values")? else {
+        return Ok(xpath_identifier());
+    };
+    let Some(standard) = only_child(path, values, xpath, "Standard")? else {
+        return Ok(xpath_identifier());
+    };
+    let Some(guid) = only_child(path, standard, xpath, "GUID")? else {
+        return Ok(xpath_identifier());
+    };
+
+    let Some(guid_value) = &guid.text else {
+        return Ok(xpath_identifier());
+    };
+
+    Ok(Identifier {
+        file_path: path.clone(),
+        kind: Kind::GUID,
+        value: guid_value.to_string(),
+    })
+}
+
+fn create_template_identifier(
+    path: &DataPath,
+    element: &Element,
+    xpath: &str,
+) -> Result<Option<Identifier>, Error> {
+    Ok(
+        only_child_text(path, element, xpath, "Name")?.map(|name_value| Identifier {
+            file_path: path.clone(),
+            kind: Kind::Name,
+            value: name_value,
+        }),
+    )
+}
+
+fn create_default_values_identifier(path: &DataPath, xpath: &str) -> Identifier {
+    Identifier {
+        file_path: path.clone(),
+        kind: Kind::XPath,
+        value: xpath.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPERTIES: &str = r#"<Properties><DefaultValues>
+  <Standard><GUID>0</GUID></Standard>
+  <FactoryBase><CycleTime>30</CycleTime><Workforce>Farmers</Workforce></FactoryBase>
+  <Electric><MaxElectricity>1</MaxElectricity></Electric>
+</DefaultValues></Properties>"#;
+
+    const TEMPLATES: &str = r#"<Templates>
+  <Template><Name>FactoryBuilding7</Name><Properties>
+    <Standard /><FactoryBase><CycleTime>60</CycleTime></FactoryBase>
+  </Properties></Template>
+</Templates>"#;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>1</GUID></Standard>
+    <FactoryBase><CycleTime>45</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>1</BaseAssetGUID><Values>
+    <Standard><GUID>2</GUID></Standard>
+  </Values></Asset>
+</AssetList>"#;
+
+    #[test]
+    fn effective_nodes_fall_back_to_the_default_values_the_template_has() {
+        let database = AssetDatabase::from_documents(PROPERTIES, TEMPLATES, ASSETS, &[]);
+        let text = |name: &str, path: &str| {
+            let path = path.split('/').map(str::to_string).collect::<Vec<_>>();
+            database
+                .effective_node(database.find(name).unwrap(), &path)
+                .map(|node| node.text.clone().unwrap_or_default())
+        };
+        let cases = [
+            ("1", "FactoryBase/CycleTime", Some("45")),
+            ("2", "FactoryBase/CycleTime", Some("45")),
+            ("FactoryBuilding7", "FactoryBase/CycleTime", Some("60")),
+            ("2", "FactoryBase/Workforce", Some("Farmers")),
+            ("FactoryBuilding7", "FactoryBase/Workforce", Some("Farmers")),
+            ("2", "Electric/MaxElectricity", None),
+            ("FactoryBuilding7", "Electric", None),
+            ("2", "Standard/Name", None),
+        ];
+        for (name, path, expected) in cases {
+            assert_eq!(text(name, path).as_deref(), expected, "{name} {path}");
+        }
+        // The DefaultValues come first and have every node of their own.
+        assert!(database
+            .effective_node(0, &["Electric".to_string()])
+            .is_some());
+    }
+}
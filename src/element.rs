@@ -0,0 +1,121 @@
+use crate::xml_structure::parse_step;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// An owned copy of an XML element, so the game data can be kept after the parsed document
+/// is dropped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    /// The text of a leaf element, branches have no text.
+    pub(crate) text: Option<String>,
+    pub(crate) children: Vec<Element>,
+    /// The ModID of the input mod whose ModOps added the element.
+    pub(crate) added_by: Option<String>,
+    /// The ModIDs of the input mods whose ModOps changed the element, in load order.
+    pub(crate) changed_by: Vec<String>,
+}
+
+impl Element {
+    pub(crate) fn from_node(node: &roxmltree::Node<'_, '_>) -> Element {
+        let children = node
+            .children()
+            .filter(|child| child.is_element())
+            .map(|child| Element::from_node(&child))
+            .collect::<Vec<_>>();
+
+        Element {
+            name: node.tag_name().name().to_string(),
+            text: match children.is_empty() {
+                true => node.text().map(|text| text.to_string()),
+                false => None,
+            },
+            children,
+            added_by: None,
+            changed_by: Vec::new(),
+        }
+    }
+
+    pub(crate) fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Returns the only child with the given name, `Err` if there is more than one.
+    pub(crate) fn child(&self, name: &str) -> Result<Option<&Element>, ()> {
+        self.children
+            .iter()
+            .filter(|child| child.name == name)
+            .at_most_one()
+            .map_err(|_| ())
+    }
+
+    /// Whether the element is addressed by the XPath step, its name or a list item like
+    /// `Item[Product='1010017']`.
+    pub(crate) fn matches_step(&self, step: &str) -> bool {
+        match parse_step(step) {
+            Some((name, None)) => self.name == name,
+            Some((name, Some((child, value)))) => {
+                self.name == name
+                    && self
+                        .children_named(child)
+                        .any(|child| child.text.as_deref().map(str::trim) == Some(value))
+            }
+            None => false,
+        }
+    }
+
+    /// The children or the text of the element as XML.
+    pub(crate) fn inner_xml(&self) -> String {
+        match &self.text {
+            Some(text) => escape(text),
+            None => self
+                .children
+                .iter()
+                .map(|child| format!("<{0}>{1}</{0}>", child.name, child.inner_xml()))
+                .collect(),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(xml: &str) -> Element {
+        Element::from_node(&roxmltree::Document::parse(xml).unwrap().root_element())
+    }
+
+    #[test]
+    fn matches_steps_by_name_or_by_key() {
+        let item = item("<Item><Product> 1010017 </Product><Amount>2</Amount></Item>");
+        for (step, expected) in [
+            ("Item", true),
+            ("Entry", false),
+            ("Item[Product='1010017']", true),
+            ("Item[Product=\"1010017\"]", true),
+            ("Item[Product=1010017]", true),
+            ("Item[ Product = '1010017' ]", true),
+            ("Item[Product='1010018']", false),
+            ("Item[Amount='2']", true),
+            ("Item[Guid='1010017']", false),
+            ("Entry[Product='1010017']", false),
+            ("Item[Product='1010017'", false),
+            ("Item[Product]", false),
+        ] {
+            assert_eq!(item.matches_step(step), expected, "{step}");
+        }
+    }
+
+    #[test]
+    fn items_without_the_key_match_no_keyed_step() {
+        let item = item("<Item><Amount>2</Amount></Item>");
+        assert!(item.matches_step("Item"));
+        assert!(!item.matches_step("Item[Product='1010017']"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{data_path::DataPath, recipe::RecipeError};
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// The recipe file could not be loaded.
+    RecipeFile(RecipeError),
+    /// A file could not be read or written.
+    Io { path: PathBuf, source: io::Error },
+    /// A file of the game data is not well-formed XML.
+    Xml {
+        path: PathBuf,
+        source: roxmltree::Error,
+    },
+    /// An `.rda` archive of the game can not be read.
+    Archive { path: PathBuf, message: String },
+    /// The `modinfo.json` of an input mod is not valid.
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A ModOp of an input mod can not be applied.
+    ModOp {
+        path: PathBuf,
+        xpath: String,
+        message: String,
+    },
+    /// Input mods wait for each other in their LoadAfterIds, so they keep their directory order.
+    LoadOrder { mod_ids: Vec<String> },
+    /// The game data does not have the expected shape, like a node that appears twice.
+    Data {
+        file: DataPath,
+        xpath: String,
+        message: String,
+    },
+    /// The recipe needs DefaultValues for its query but none of the properties files has them.
+    MissingDefaultValues { query: String },
+    /// No asset has the GUID and no template has the name asked for.
+    UnknownEntry { name: String },
+    /// No recipe has the name asked for.
+    UnknownRecipe { name: String },
+    /// A problem that came up while creating the mod of a recipe.
+    Recipe { recipe: String, source: Box<Error> },
+}
+
+impl Error {
+    pub(crate) fn in_recipe(self, recipe: &str) -> Error {
+        Error::Recipe {
+            recipe: recipe.to_string(),
+            source: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RecipeFile(error) => write!(f, "{error}"),
+            Error::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::Xml { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::Archive { path, message } => write!(f, "{}: {message}", path.display()),
+            Error::Json { path, source } => write!(f, "{}: {source}", path.display()),
+            Error::ModOp {
+                path,
+                xpath,
+                message,
+            } => write!(f, "{} {xpath}: {message}", path.display()),
+            Error::LoadOrder { mod_ids } => write!(
+                f,
+                "These mods can not be ordered by their LoadAfterIds: {}",
+                mod_ids.join(", ")
+            ),
+            Error::Data {
+                file,
+                xpath,
+                message,
+            } => write!(f, "{file} {xpath}: {message}"),
+            Error::MissingDefaultValues { query } => {
+                write!(f, "No DefaultValues contain '{query}'")
+            }
+            Error::UnknownEntry { name } => {
+                write!(
+                    f,
+                    "No asset with the GUID '{name}' and no template with that name"
+                )
+            }
+            Error::UnknownRecipe { name } => write!(f, "No recipe with the name '{name}'"),
+            Error::Recipe { recipe, source } => write!(f, "[{recipe}] {source}"),
+        }
+    }
+}
+
+/// Prints all collected problems to stderr.
+pub(crate) fn print_report(errors: &[Error]) {
+    if errors.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "{} problem{} found:",
+        errors.len(),
+        if errors.len() == 1 { "" } else { "s" }
+    );
+    errors.iter().for_each(|error| eprintln!("  {error}"));
+}
+
+/// Prints problems that do not stop the program to stderr, like recipe entries matching nothing.
+pub(crate) fn print_warnings(warnings: &[impl fmt::Display]) {
+    if warnings.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "{} warning{}:",
+        warnings.len(),
+        if warnings.len() == 1 { "" } else { "s" }
+    );
+    warnings.iter().for_each(|warning| eprintln!("  {warning}"));
+}
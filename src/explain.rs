@@ -0,0 +1,144 @@
+use crate::{
+    database::{AssetDatabase, Entry, NodeType},
+    error::Error,
+    helper::{self, ModOpsKind},
+    identifier::Kind,
+    inspect, mod_builder,
+    recipe::Recipe,
+    state::Reason,
+    xml_node::{XmlNode, XmlNodeData},
+};
+use std::fmt::Write;
+
+/// Explains how the recipe treats the asset with the GUID or the template with the name: the
+/// rules its state comes from, following the inheritance up to the rule that decided it, and the
+/// kind of ModOp created for every node of the query.
+pub(crate) fn explain(
+    database: &AssetDatabase,
+    recipes: &[Recipe],
+    recipe_name: &str,
+    name: &str,
+) -> Result<String, Error> {
+    let recipe = recipes
+        .iter()
+        .find(|recipe| recipe.name == recipe_name)
+        .ok_or_else(|| Error::UnknownRecipe {
+            name: recipe_name.to_string(),
+        })?;
+    let index = database.find(name).ok_or_else(|| Error::UnknownEntry {
+        name: name.to_string(),
+    })?;
+    let entry = &database.entries[index];
+
+    let (mod_entries, _) = mod_builder::build(recipe, database);
+    let mod_entry_of = |entry: &Entry| {
+        mod_entries
+            .iter()
+            .find(|mod_entry| std::ptr::eq(mod_entry.entry, entry))
+    };
+
+    let mut text = String::new();
+    let _ = writeln!(text, "{}, {}", recipe.name, label(database, entry));
+    let Some(mod_entry) = mod_entry_of(entry) else {
+        let _ = writeln!(
+            text,
+            "  Not part of the mod, it does not get {} from a template",
+            recipe.query.name
+        );
+        return Ok(text);
+    };
+
+    let _ = writeln!(text, "  State: {}", mod_entry.state);
+    let mut current = mod_entry;
+    loop {
+        let _ = write!(
+            text,
+            "    {} is {}, {}",
+            inspect::named_label(database, current.entry),
+            current.state,
+            current.reason
+        );
+        if current.reason == Reason::ForcedGuid
+            && recipe
+                .excluded_guids
+                .contains(&current.entry.identifier.value)
+        {
+            let _ = write!(text, ", which wins over excluded_guids");
+        }
+        let _ = writeln!(text);
+
+        let parent = match current.reason {
+            Reason::Inherited => database
+                .parent_index(&current.entry.parent)
+                .and_then(|parent_index| mod_entry_of(&database.entries[parent_index])),
+            _ => None,
+        };
+        match parent {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+
+    let mut nodes = Vec::new();
+    collect_nodes(&mod_entry.content, &mut Vec::new(), &mut nodes);
+    let width = nodes
+        .iter()
+        .map(|(path, _)| path.join("/").len())
+        .max()
+        .unwrap_or_default();
+    for (path, node) in nodes {
+        let kind = match helper::mod_ops_kind_at(mod_entry, &recipe.query, &path) {
+            Ok(ModOpsKind::ReplaceValue(value)) => format!("ReplaceValue {value}"),
+            Ok(ModOpsKind::AddValue(value)) => format!("AddValue {}", single_line(&value)),
+            Ok(ModOpsKind::AddNode) => "AddNode".to_string(),
+            Ok(ModOpsKind::AddList(_)) => "AddList, a copy of the inherited list".to_string(),
+            Ok(ModOpsKind::Remove) => "Remove".to_string(),
+            Ok(ModOpsKind::None) => "None".to_string(),
+            Err(message) => message,
+        };
+        let origin = match (&node.data, node.present) {
+            (XmlNodeData::Leaf(value), true) => format!("set here to {value}"),
+            (XmlNodeData::Leaf(value), false) => format!("inherits {value}"),
+            (XmlNodeData::Raw(_), true) => "set here".to_string(),
+            (XmlNodeData::Raw(_), false) => "inherited".to_string(),
+            (XmlNodeData::Branch(_), true) => "present".to_string(),
+            (XmlNodeData::Branch(_), false) => "not present".to_string(),
+            (XmlNodeData::None, _) => "not set anywhere".to_string(),
+        };
+        let _ = writeln!(text, "  {:<width$}  {kind}, {origin}", path.join("/"));
+    }
+
+    Ok(text)
+}
+
+fn label(database: &AssetDatabase, entry: &Entry) -> String {
+    match (entry.node_type, &entry.identifier.kind) {
+        (NodeType::DefaultValues, _) => format!("DefaultValues {}", entry.xpath),
+        (NodeType::Template, _) => format!("template {}", entry.identifier.value),
+        (NodeType::Asset, Kind::GUID) => {
+            format!("asset {}", inspect::named_label(database, entry))
+        }
+        (NodeType::Asset, _) => format!("asset {} {}", entry.identifier.file_path, entry.xpath),
+    }
+}
+
+/// The nodes of the content with their paths, parents before their children.
+fn collect_nodes<'a>(
+    node: &'a XmlNode,
+    path: &mut Vec<String>,
+    nodes: &mut Vec<(Vec<String>, &'a XmlNode)>,
+) {
+    path.push(node.name.clone());
+    nodes.push((path.clone(), node));
+    if let XmlNodeData::Branch(children) = &node.data {
+        children
+            .iter()
+            .for_each(|child| collect_nodes(child, path, nodes));
+    }
+    path.pop();
+}
+
+/// The inner XML of a removed node can span lines, it is shown on one.
+fn single_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
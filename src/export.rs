@@ -0,0 +1,168 @@
+use crate::{
+    arguments::ExportFormat,
+    database::{AssetDatabase, NodeType},
+    error::Error,
+    mod_builder,
+    recipe::Recipe,
+    state::State,
+    transform,
+    xml_node::{XmlNode, XmlNodeData},
+    xml_structure::{Content, XmlTag},
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// A value of an asset the query of a recipe finds, before and after the recipe's mod.
+#[derive(Debug, Serialize)]
+pub(crate) struct Row {
+    recipe: String,
+    guid: String,
+    name: Option<String>,
+    template: Option<String>,
+    file: String,
+    source: String,
+    path: String,
+    /// The value the asset has in the game data, set by itself or inherited.
+    old: Option<String>,
+    /// The value the asset has with the mod, `None` once the node is removed.
+    new: Option<String>,
+    state: String,
+}
+
+/// The old and new value of a row.
+type Values = (Option<String>, Option<String>);
+
+/// The rows of every asset of every recipe, in the order of the recipes and the game data.
+pub(crate) fn collect_rows(database: &AssetDatabase, recipes: &[Recipe]) -> (Vec<Row>, Vec<Error>) {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for recipe in recipes {
+        let (mod_entries, mod_errors) = mod_builder::build(recipe, database);
+        errors.extend(
+            mod_errors
+                .into_iter()
+                .map(|error| error.in_recipe(&recipe.name)),
+        );
+
+        for mod_entry in mod_entries
+            .iter()
+            .filter(|mod_entry| mod_entry.entry.node_type == NodeType::Asset)
+        {
+            let entry = mod_entry.entry;
+            let mut leaves = Vec::new();
+            collect_leaves(
+                &mod_entry.content,
+                &recipe.query,
+                &mut Vec::new(),
+                &mut leaves,
+            );
+            for (path, node, content) in leaves {
+                let (old, new) = match before_and_after(node, content, &mod_entry.state) {
+                    Ok(Some(values)) => values,
+                    Ok(None) => continue,
+                    Err(message) => {
+                        errors.push(
+                            Error::Data {
+                                file: entry.identifier.file_path.clone(),
+                                xpath: entry.xpath.clone(),
+                                message,
+                            }
+                            .in_recipe(&recipe.name),
+                        );
+                        continue;
+                    }
+                };
+                rows.push(Row {
+                    recipe: recipe.name.clone(),
+                    guid: entry.identifier.value.clone(),
+                    name: database.name(entry).map(str::to_string),
+                    template: database
+                        .template_of(entry)
+                        .map(|template| template.identifier.value.clone()),
+                    file: entry.identifier.file_path.to_string(),
+                    source: entry.origin.source.to_string(),
+                    path,
+                    old,
+                    new,
+                    state: mod_entry.state.to_string(),
+                });
+            }
+        }
+    }
+    (rows, errors)
+}
+
+/// The leaves and removed nodes of the content with their paths and what the query does with
+/// them.
+fn collect_leaves<'a>(
+    node: &'a XmlNode,
+    query: &'a XmlTag,
+    path: &mut Vec<String>,
+    leaves: &mut Vec<(String, &'a XmlNode, &'a Content)>,
+) {
+    path.push(node.name.clone());
+    match (&node.data, &query.content) {
+        (XmlNodeData::Branch(children), Content::Branch(query_children)) => {
+            children.iter().for_each(|child| {
+                if let Some(query_child) = query_children
+                    .iter()
+                    .find(|query_child| query_child.matches(&child.name))
+                {
+                    collect_leaves(child, query_child, path, leaves);
+                }
+            })
+        }
+        (_, Content::Branch(_)) => (),
+        (_, content) => leaves.push((path.join("/"), node, content)),
+    }
+    path.pop();
+}
+
+/// The effective value of the node without and with the mod, `None` if the asset neither has
+/// nor inherits it.
+///
+/// An entry the mod changes either sets the new value itself or inherits it from an ancestor that
+/// is changed as well, the others keep their value.
+fn before_and_after(
+    node: &XmlNode,
+    content: &Content,
+    state: &State,
+) -> Result<Option<Values>, String> {
+    let is_changed = matches!(
+        state,
+        State::Included | State::Forced | State::ForcedByAncestor
+    );
+    Ok(match (&node.data, content) {
+        (XmlNodeData::Leaf(old), Content::Leaf(transforms)) => {
+            let new = match is_changed {
+                true => transform::apply(transforms, old)
+                    .map_err(|error| format!("Could not transform {}: {error}", node.name))?,
+                false => old.clone(),
+            };
+            Some((Some(old.to_string()), Some(new.to_string())))
+        }
+        (XmlNodeData::Raw(_), Content::Remove) => {
+            let new = match is_changed {
+                true => None,
+                false => Some("present".to_string()),
+            };
+            Some((Some("present".to_string()), new))
+        }
+        _ => None,
+    })
+}
+
+pub(crate) fn write(path: &Path, rows: &[Row], format: ExportFormat) -> Result<(), Error> {
+    let bytes = match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            rows.iter().for_each(|row| writer.serialize(row).unwrap());
+            writer.into_inner().unwrap()
+        }
+        ExportFormat::Json => serde_json::to_vec_pretty(rows).unwrap(),
+    };
+    std::fs::write(path, bytes).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
@@ -1,250 +1,375 @@
 use crate::{
-    identifier::{self, Identifier},
+    arguments::{ModOpStyle, OptionalSources},
+    data_path::DataPath,
+    database::{AssetDatabase, NodeType},
+    element::Element,
+    error::Error,
+    identifier,
+    layer::{DataFile, Layer},
+    mod_builder::ModEntry,
+    modinfo::ModInfo,
+    rda::Archive,
+    recipe::Recipe,
+    source::Source,
     state::State,
+    transform,
+    value::Value,
     xml_node::{XmlNode, XmlNodeData},
-    xml_structure::{Content, XmlTag},
-    NodeType,
+    xml_structure::{Content, Placement, XmlTag},
 };
-use core::panic;
-use itertools::Itertools;
-use roxmltree::Node;
+use rayon::prelude::*;
 use std::io::Write;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use walkdir::WalkDir;
 
-pub(crate) fn get_paths(path: &PathBuf) -> (Vec<PathBuf>, Vec<PathBuf>, Vec<PathBuf>) {
+/// The file names of the game data that is read.
+const DATA_FILE_NAMES: [&str; 3] = ["properties.xml", "templates.xml", "assets.xml"];
+
+pub(crate) struct DataFiles {
+    pub(crate) properties: Vec<DataFile>,
+    pub(crate) templates: Vec<DataFile>,
+    pub(crate) assets: Vec<DataFile>,
+    /// The `texts_<language>.xml` files of the chosen language.
+    pub(crate) texts: Vec<DataFile>,
+}
+
+/// The properties, templates, assets and texts files of all layers, in load order.
+///
+/// The `.rda` archives of a layer are read in the order of their numbers, followed by the files
+/// on disk. A file found again in the same layer replaces the earlier one, like in the game.
+/// Archives that can not be read are left out, the problems are returned alongside.
+pub(crate) fn get_paths(layers: &[Layer], language: &str) -> (DataFiles, Vec<Error>) {
+    let texts_file_name = format!("texts_{}.xml", language.to_lowercase());
+    let is_data_file = |path: &str| {
+        path.rsplit(['/', '\\'])
+            .next()
+            .is_some_and(|name| DATA_FILE_NAMES.contains(&name) || name == texts_file_name)
+    };
     let mut properties_paths = Vec::new();
     let mut templates_paths = Vec::new();
     let mut assets_paths = Vec::new();
+    let mut texts_paths = Vec::new();
+    let mut errors = Vec::new();
 
-    WalkDir::new(path)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|entry| entry.file_type().is_file())
-        .for_each(|entry| match entry.file_name().to_str() {
-            Some("properties.xml") => properties_paths.push(entry.path().to_path_buf()),
-            Some("templates.xml") => templates_paths.push(entry.path().to_path_buf()),
-            Some("assets.xml") => assets_paths.push(entry.path().to_path_buf()),
-            _ => (),
-        });
-
-    properties_paths.sort();
-    templates_paths.sort();
-    assets_paths.sort();
+    layers.iter().for_each(|layer| {
+        let mut layer_paths = WalkDir::new(&layer.path)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .collect::<Vec<_>>();
+        layer_paths.sort();
+        let (mut archive_paths, disk_paths): (Vec<_>, Vec<_>) =
+            layer_paths.into_iter().partition(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("rda"))
+            });
+        archive_paths.sort_by_cached_key(|path| archive_order(path));
 
-    (properties_paths, templates_paths, assets_paths)
-}
+        let mut layer_files: Vec<DataFile> = Vec::new();
+        let mut data_path_indices: HashMap<DataPath, usize> = HashMap::new();
+        let mut add = |file: DataFile| match data_path_indices.get(&file.data_path) {
+            Some(&index) => layer_files[index] = file,
+            None => {
+                data_path_indices.insert(file.data_path.clone(), layer_files.len());
+                layer_files.push(file);
+            }
+        };
 
-pub(crate) fn get_xpath(node: &roxmltree::Node) -> String {
-    node.ancestors()
-        .filter(|node| !node.is_root())
-        .map(|node| {
-            let tag_name = node.tag_name().name().to_string();
-            let index = node
-                .parent()
-                .unwrap()
-                .children()
-                .filter(|child| child.tag_name().name() == tag_name)
-                .position(|child| child == node)
-                .unwrap();
-            format!("{}[{}]", tag_name, index + 1)
-        })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .fold(String::new(), |acc, x| format!("{}/{}", acc, x))
-}
+        let archives = archive_paths
+            .par_iter()
+            .map(|path| Archive::open(path))
+            .collect::<Vec<_>>();
+        for archive in archives {
+            let archive = match archive {
+                Ok(archive) => Arc::new(archive),
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            (0..archive.files.len())
+                .filter(|&index| is_data_file(&archive.files[index].name))
+                .for_each(|index| add(DataFile::archived(&archive, index)));
+        }
+        disk_paths
+            .into_iter()
+            .filter(|path| is_data_file(&path.to_string_lossy()))
+            .for_each(|path| add(DataFile::on_disk(layer, path)));
 
-pub(crate) fn has_properties_child(node: &Node<'_, '_>, query: &XmlTag) -> bool {
-    let Some(properties_node) = node
-        .children()
-        .filter(|child| child.tag_name().name() == "Properties")
-        .at_most_one()
-        .expect(
-            format!(
-                "More than one Properties node found in node {}",
-                get_xpath(&node)
-            )
-            .as_str(),
-        )
-    else {
-        return false;
-    };
+        layer_files
+            .into_iter()
+            .for_each(|file| match file.data_path.components().last() {
+                Some("properties.xml") => properties_paths.push(file),
+                Some("templates.xml") => templates_paths.push(file),
+                Some("assets.xml") => assets_paths.push(file),
+                Some(_) => texts_paths.push(file),
+                None => (),
+            });
+    });
 
-    has_direct_child(&properties_node, query)
+    (
+        DataFiles {
+            properties: properties_paths,
+            templates: templates_paths,
+            assets: assets_paths,
+            texts: texts_paths,
+        },
+        errors,
+    )
 }
 
-pub(crate) fn has_direct_child(node: &Node<'_, '_>, query: &XmlTag) -> bool {
-    node.children()
-        .filter(|child| child.tag_name().name() == query.name)
-        .count()
-        == 1
+/// Orders archives by the number at the end of their name, so `data10.rda` comes after
+/// `data9.rda`.
+fn archive_order(path: &Path) -> (PathBuf, String, u64) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    (
+        path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        prefix.to_string(),
+        stem[prefix.len()..].parse().unwrap_or_default(),
+    )
 }
 
-pub(crate) fn extract_content_from_values(
-    node: &Node<'_, '_>,
-    query: &XmlTag,
-    parent_content: Option<&XmlNode>,
-) -> Option<XmlNode> {
-    let values_node = node
-        .children()
-        .filter(|child| child.tag_name().name() == "Values")
-        .at_most_one()
-        .expect(
-            format!(
-                "More than one Values node found in node {}",
-                get_xpath(&node)
-            )
-            .as_str(),
-        )
-        .expect(format!("No Values node found in node {}", get_xpath(&node)).as_str());
-
-    extract_content(&values_node, query, parent_content)
+pub(crate) fn has_direct_child(node: &Element, name: &str) -> bool {
+    node.children_named(name).count() == 1
 }
 
-pub(crate) fn extract_content_from_properties(
-    node: &Node<'_, '_>,
+pub(crate) fn extract_content(
+    node: &Element,
     query: &XmlTag,
     parent_content: Option<&XmlNode>,
-) -> Option<XmlNode> {
-    let Some(properties_node) = node
-        .children()
-        .filter(|child| child.tag_name().name() == "Properties")
-        .at_most_one()
-        .expect(
-            format!(
-                "More than one Properties node found in node {}",
-                get_xpath(&node)
-            )
-            .as_str(),
-        )
+    file: &DataPath,
+    xpath: &str,
+) -> Result<Option<XmlNode>, Error> {
+    let Some(child_node) = node.child(&query.name).map_err(|_| Error::Data {
+        file: file.clone(),
+        xpath: xpath.to_string(),
+        message: format!("More than one {} node found", query.name),
+    })?
     else {
-        return create_content(query, parent_content);
+        return Ok(create_content(query, &query.name, parent_content));
     };
-
-    extract_content(&properties_node, query, parent_content)
+    content_of(child_node, query, &query.name, parent_content, file, xpath)
 }
 
-pub(crate) fn extract_content(
-    node: &Node<'_, '_>,
+/// The content of the node the query matched, named by the step that addresses it.
+fn content_of(
+    child_node: &Element,
     query: &XmlTag,
+    name: &str,
     parent_content: Option<&XmlNode>,
-) -> Option<XmlNode> {
-    let Some(child_node) = node
-        .children()
-        .filter(|child| child.tag_name().name() == query.name)
-        .at_most_one()
-        .expect(
-            format!(
-                "More than one {} node found in node {}",
-                query.name,
-                get_xpath(&node)
-            )
-            .as_str(),
-        )
-    else {
-        return create_content(query, parent_content);
-    };
-
+    file: &DataPath,
+    xpath: &str,
+) -> Result<Option<XmlNode>, Error> {
     match &query.content {
         Content::Branch(query_children) => {
+            let child_xpath = format!("{}/{}", xpath, name);
             let created_children = query_children
                 .iter()
-                .filter_map(|query_child| {
-                    let parent_content_child = match parent_content {
-                        Some(parent_content) => match &parent_content.data {
-                            XmlNodeData::Branch(children) => {
-                                children.iter().find(|child| child.name == query_child.name)
-                            }
-                            _ => None,
-                        },
-                        None => None,
-                    };
-                    extract_content(&child_node, query_child, parent_content_child)
+                .map(|query_child| match &query_child.key {
+                    None => extract_content(
+                        child_node,
+                        query_child,
+                        parent_child(parent_content, query_child),
+                        file,
+                        &child_xpath,
+                    )
+                    .map(Vec::from_iter),
+                    Some(_) => extract_items(child_node, query_child, file, &child_xpath),
                 })
-                .collect::<Vec<_>>();
-            Some(XmlNode {
-                name: query.name.clone(),
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Some(XmlNode {
+                name: name.to_string(),
                 present: true,
-                data: XmlNodeData::Branch(created_children),
-            })
+                data: XmlNodeData::Branch(created_children.into_iter().flatten().collect()),
+                list: is_list(query_children).then(|| child_node.clone()),
+            }))
         }
-        Content::Leaf => {
-            let Some(text) = child_node.text().and_then(|text| Some(text.to_string())) else {
-                return None;
+        Content::Leaf(_) => {
+            let Some(text) = child_node.text.as_deref() else {
+                return Ok(None);
             };
-            Some(XmlNode {
-                name: query.name.clone(),
+            Ok(Some(XmlNode {
+                name: name.to_string(),
                 present: true,
-                data: XmlNodeData::Leaf(text),
-            })
+                data: XmlNodeData::Leaf(Value::parse(text)),
+                list: None,
+            }))
         }
+        Content::Remove => Ok(Some(XmlNode {
+            name: name.to_string(),
+            present: true,
+            data: XmlNodeData::Raw(child_node.inner_xml()),
+            list: None,
+        })),
     }
 }
 
-fn create_content(query: &XmlTag, parent_content: Option<&XmlNode>) -> Option<XmlNode> {
+/// The items of the list the keyed query selects, in the order of the list. Items without the
+/// key child are left out.
+///
+/// A list is replaced as a whole by an entry defining it, so the items inherit nothing from the
+/// items of the parent.
+fn extract_items(
+    list: &Element,
+    query: &XmlTag,
+    file: &DataPath,
+    xpath: &str,
+) -> Result<Vec<XmlNode>, Error> {
+    let Some(key) = &query.key else {
+        return Ok(Vec::new());
+    };
+    let mut steps = HashSet::new();
+    list.children_named(&query.name)
+        .filter_map(|item| {
+            let value = item
+                .children_named(&key.child)
+                .next()?
+                .text
+                .as_deref()?
+                .trim();
+            key.value
+                .as_deref()
+                .is_none_or(|wanted| wanted == value)
+                .then_some((item, value))
+        })
+        .map(|(item, value)| {
+            let step = query.item_step(value);
+            if !steps.insert(step.clone()) {
+                return Err(Error::Data {
+                    file: file.clone(),
+                    xpath: xpath.to_string(),
+                    message: format!("More than one {step} node found"),
+                });
+            }
+            content_of(item, query, &step, None, file, xpath)
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+fn create_content(query: &XmlTag, name: &str, parent_content: Option<&XmlNode>) -> Option<XmlNode> {
     match &query.content {
         Content::Branch(query_children) => {
             let created_children = query_children
                 .iter()
-                .filter_map(|query_child| {
-                    let parent_content_child = match parent_content {
-                        Some(parent_content) => match &parent_content.data {
-                            XmlNodeData::Branch(children) => {
-                                children.iter().find(|child| child.name == query_child.name)
-                            }
-                            _ => None,
-                        },
-                        None => None,
-                    };
-                    create_content(query_child, parent_content_child)
+                .flat_map(|query_child| match &query_child.key {
+                    None => create_content(
+                        query_child,
+                        &query_child.name,
+                        parent_child(parent_content, query_child),
+                    )
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+                    // The items of an inherited list are inherited as they are.
+                    Some(_) => parent_children(parent_content)
+                        .iter()
+                        .filter(|child| query_child.matches(&child.name))
+                        .filter_map(|child| create_content(query_child, &child.name, Some(child)))
+                        .collect(),
                 })
                 .collect::<Vec<_>>();
-            return Some(XmlNode {
-                name: query.name.clone(),
-                present: false,
-                data: XmlNodeData::Branch(created_children.to_vec()),
-            });
-        }
-        Content::Leaf => {
-            return Some(XmlNode {
-                name: query.name.clone(),
+            Some(XmlNode {
+                name: name.to_string(),
                 present: false,
-                data: parent_content
-                    .map(|parent_content| match &parent_content.data {
-                        XmlNodeData::Branch(_) => XmlNodeData::None,
-                        XmlNodeData::Leaf(x) => XmlNodeData::Leaf(x.clone()),
-                        XmlNodeData::None => XmlNodeData::None,
-                    })
-                    .unwrap_or(XmlNodeData::None),
-            });
+                data: XmlNodeData::Branch(created_children),
+                list: parent_content.and_then(|parent_content| parent_content.list.clone()),
+            })
         }
+        Content::Leaf(_) | Content::Remove => Some(XmlNode {
+            name: name.to_string(),
+            present: false,
+            data: parent_content
+                .map(|parent_content| match &parent_content.data {
+                    XmlNodeData::Branch(_) => XmlNodeData::None,
+                    XmlNodeData::Leaf(x) => XmlNodeData::Leaf(x.clone()),
+                    XmlNodeData::Raw(x) => XmlNodeData::Raw(x.clone()),
+                    XmlNodeData::None => XmlNodeData::None,
+                })
+                .unwrap_or(XmlNodeData::None),
+            list: None,
+        }),
     }
 }
 
-pub(crate) fn write_mod(
-    output_path: &PathBuf,
-    mod_name: &str,
-    identifiers: &Vec<Identifier>,
-    node_types: &HashMap<Identifier, NodeType>,
-    states: &HashMap<Identifier, State>,
-    contents: &HashMap<Identifier, XmlNode>,
-) {
-    let mod_path = output_path.join(enhanced_name(mod_name));
+fn parent_children(parent_content: Option<&XmlNode>) -> &[XmlNode] {
+    match parent_content.map(|parent_content| &parent_content.data) {
+        Some(XmlNodeData::Branch(children)) => children,
+        _ => &[],
+    }
+}
 
-    delete_mod_files(&mod_path);
+fn parent_child<'a>(
+    parent_content: Option<&'a XmlNode>,
+    query_child: &XmlTag,
+) -> Option<&'a XmlNode> {
+    parent_children(parent_content)
+        .iter()
+        .find(|child| query_child.matches(&child.name))
+}
+
+/// Whether the node holds the items of a list, selected by a key.
+fn is_list(query_children: &[XmlTag]) -> bool {
+    query_children
+        .iter()
+        .any(|query_child| query_child.key.is_some())
+}
 
-    create_mod_directory(&mod_path);
+/// The ModOps of a mod, grouped by the file they patch.
+#[derive(Debug, Default)]
+pub(crate) struct ModFiles {
+    pub(crate) path_vs_mod_ops: HashMap<DataPath, Vec<ModOp>>,
+    /// The ModIDs of the input mods whose data gets changed, the mod has to load after them.
+    pub(crate) patched_mod_ids: BTreeSet<String>,
+}
 
-    let mut path_vs_mod_ops: HashMap<PathBuf, Vec<ModOp>> = HashMap::new();
+/// Creates the ModOps of every entry.
+///
+/// Entries whose ModOps cannot be created are left out, the problems are returned alongside.
+pub(crate) fn create_mod_ops(
+    recipe: &Recipe,
+    mod_entries: &[ModEntry],
+    database: &AssetDatabase,
+    style: ModOpStyle,
+    optional_sources: OptionalSources,
+) -> (ModFiles, Vec<Error>) {
+    let query = &recipe.query;
+    let mut mod_files = ModFiles::default();
+    let path_vs_mod_ops = &mut mod_files.path_vs_mod_ops;
+    let mut errors = Vec::new();
+    // The position of the ModOps of every distinct set of changes already written for a GUID.
+    // Only whole sets are shared, so the ModOps of each asset keep their order, an added node
+    // always comes before the values added to it.
+    let mut batches: HashMap<(DataPath, Vec<ModOp>), Range<usize>> = HashMap::new();
 
-    identifiers.into_iter().for_each(|identifier| {
+    mod_entries.iter().for_each(|mod_entry| {
+        let identifier = &mod_entry.entry.identifier;
         let file_path = identifier.file_path.clone();
-        let content = contents.get(identifier).unwrap();
-        let state = states.get(identifier).unwrap();
-        let node_type = node_types.get(identifier).unwrap();
+        let content = &mod_entry.content;
+        let state = &mod_entry.state;
+        let node_type = &mod_entry.entry.node_type;
+        let data_error = |message| Error::Data {
+            file: file_path.clone(),
+            xpath: mod_entry.entry.xpath.clone(),
+            message,
+        };
 
-        let mod_ops_structure = create_mod_ops_structure(content, state);
+        let mod_ops_structure = match create_mod_ops_structure(content, query, state) {
+            Ok(mod_ops_structure) => mod_ops_structure,
+            Err(message) => {
+                errors.push(data_error(message));
+                return;
+            }
+        };
 
         if !are_any_changes_required(&mod_ops_structure) {
             return;
@@ -252,48 +377,148 @@ pub(crate) fn write_mod(
 
         let mod_op_path_root = match identifier.kind {
             identifier::Kind::XPath => match node_type {
-                NodeType::DefaultValues => format!("{}", identifier.value),
+                NodeType::DefaultValues => identifier.value.to_string(),
                 NodeType::Asset => format!("{}/Values", identifier.value),
                 _ => {
-                    panic!(
+                    errors.push(data_error(format!(
                         "Unsupported node type for XPath identifier: {:?}",
                         node_type
-                    );
+                    )));
+                    return;
                 }
             },
 
             identifier::Kind::Name => format!("//Template[Name='{}']/Properties", identifier.value),
-            identifier::Kind::GUID => format!(
-                "//Asset[Values/Standard/GUID = '{}']/Values",
-                identifier.value
-            ),
+            identifier::Kind::GUID => match style {
+                ModOpStyle::XPath => format!(
+                    "//Asset[Values/Standard/GUID = '{}']/Values",
+                    identifier.value
+                ),
+                ModOpStyle::Guid | ModOpStyle::GuidBatched => "/Values".to_string(),
+            },
         };
 
-        let mod_ops = convert_mod_ops_structure_to_mod_ops(mod_op_path_root, &mod_ops_structure);
+        let mut mod_ops =
+            convert_mod_ops_structure_to_mod_ops(mod_op_path_root, &mod_ops_structure);
 
-        path_vs_mod_ops
-            .entry(file_path)
-            .or_insert_with(Vec::new)
-            .extend(mod_ops);
-    });
+        let origin = &mod_entry.entry.origin;
+        if let Source::Mod(mod_id) = &origin.source {
+            mod_files.patched_mod_ids.insert(mod_id.clone());
+        }
+        mod_files
+            .patched_mod_ids
+            .extend(origin.changed_by.iter().cloned());
 
-    path_vs_mod_ops.iter().for_each(|(path, mod_ops)| {
-        let full_path = mod_path.join(path);
-        let parent_path = full_path.parent().unwrap();
-        std::fs::create_dir_all(parent_path).unwrap();
-        std::fs::File::create(&full_path).unwrap();
-        let mut file = std::fs::OpenOptions::new()
-            .write(true)
-            .open(full_path)
-            .unwrap();
-        writeln!(file, "<ModOps>").unwrap();
-        mod_ops.iter().for_each(|mod_op| {
-            mod_op.to_xml().iter().for_each(|line| {
-                writeln!(file, "  {}", line).unwrap();
-            });
+        // Data of DLCs and other mods may be missing in the game, the ModOps should then neither
+        // apply nor fill the mod loader's log with warnings.
+        if origin.source.is_optional() {
+            match optional_sources {
+                OptionalSources::Condition => {
+                    let condition = match identifier.kind {
+                        identifier::Kind::XPath => identifier.value.clone(),
+                        identifier::Kind::Name => {
+                            format!("//Template[Name='{}']", identifier.value)
+                        }
+                        identifier::Kind::GUID => {
+                            format!("//Asset[Values/Standard/GUID='{}']", identifier.value)
+                        }
+                    };
+                    mod_ops
+                        .iter_mut()
+                        .for_each(|mod_op| mod_op.condition = Some(condition.clone()));
+                }
+                OptionalSources::AllowNoMatch => mod_ops
+                    .iter_mut()
+                    .for_each(|mod_op| mod_op.allow_no_match = true),
+                OptionalSources::None => (),
+            }
+        }
+
+        // The name of the asset is written above its ModOps, so they can be read without looking
+        // up the GUIDs.
+        let name = database.name(mod_entry.entry).map(str::to_string);
+        if identifier.kind != identifier::Kind::GUID || style == ModOpStyle::XPath {
+            mod_ops
+                .iter_mut()
+                .for_each(|mod_op| mod_op.names.extend(name.clone()));
+            path_vs_mod_ops
+                .entry(file_path)
+                .or_default()
+                .extend(mod_ops);
+            return;
+        }
+
+        let file_mod_ops = path_vs_mod_ops.entry(file_path.clone()).or_default();
+        if style == ModOpStyle::GuidBatched {
+            if let Some(range) = batches.get(&(file_path.clone(), mod_ops.clone())) {
+                file_mod_ops[range.clone()].iter_mut().for_each(|mod_op| {
+                    mod_op.guids.push(identifier.value.clone());
+                    mod_op.names.extend(name.clone());
+                });
+                return;
+            }
+            let start = file_mod_ops.len();
+            batches.insert((file_path, mod_ops.clone()), start..start + mod_ops.len());
+        }
+        mod_ops.iter_mut().for_each(|mod_op| {
+            mod_op.guids.push(identifier.value.clone());
+            mod_op.names.extend(name.clone());
         });
-        writeln!(file, "</ModOps>").unwrap();
+        file_mod_ops.extend(mod_ops);
     });
+
+    (mod_files, errors)
+}
+
+pub(crate) fn write_mod(
+    output_path: &Path,
+    recipe: &Recipe,
+    mod_files: &ModFiles,
+) -> Result<(), Error> {
+    let display_name = enhanced_name(&recipe.name);
+    let mod_path = mod_path(output_path, recipe);
+
+    delete_mod_files(&mod_path)?;
+
+    create_mod_directory(&mod_path)?;
+
+    let modinfo = ModInfo::new(&display_name, &recipe.modinfo, &mod_files.patched_mod_ids);
+    let modinfo_path = mod_path.join("modinfo.json");
+    std::fs::write(&modinfo_path, modinfo.to_json()).map_err(|source| Error::Io {
+        path: modinfo_path,
+        source,
+    })?;
+
+    mod_files
+        .path_vs_mod_ops
+        .iter()
+        .try_for_each(|(path, mod_ops)| {
+            let full_path = mod_path.join(path.to_path_buf());
+            write_mod_ops_file(&full_path, mod_ops).map_err(|source| Error::Io {
+                path: full_path,
+                source,
+            })
+        })
+}
+
+/// The directory the mod of the recipe is written to.
+pub(crate) fn mod_path(output_path: &Path, recipe: &Recipe) -> PathBuf {
+    output_path.join(enhanced_name(&recipe.name))
+}
+
+fn write_mod_ops_file(full_path: &Path, mod_ops: &[ModOp]) -> std::io::Result<()> {
+    if let Some(parent_path) = full_path.parent() {
+        std::fs::create_dir_all(parent_path)?;
+    }
+    let mut file = std::io::BufWriter::new(std::fs::File::create(full_path)?);
+    writeln!(file, "<ModOps>")?;
+    for mod_op in mod_ops {
+        for line in mod_op.to_xml() {
+            writeln!(file, "  {}", line)?;
+        }
+    }
+    writeln!(file, "</ModOps>")?;
+    file.flush()
 }
 
 fn convert_mod_ops_structure_to_mod_ops(
@@ -301,213 +526,562 @@ fn convert_mod_ops_structure_to_mod_ops(
     mod_ops_structure: &ModOpsStructure,
 ) -> Vec<ModOp> {
     let mut mod_ops = Vec::new();
+    let name = &mod_ops_structure.name;
+    let path = format!("{}/{}", mod_op_root_path, name);
+    let (add_type, add_path) = match &mod_ops_structure.placement {
+        None => ("Add", mod_op_root_path.clone()),
+        Some(Placement::After(sibling)) => (
+            "AddNextSibling",
+            format!("{}/{}", mod_op_root_path, sibling),
+        ),
+        Some(Placement::Before(sibling)) => (
+            "AddPrevSibling",
+            format!("{}/{}", mod_op_root_path, sibling),
+        ),
+    };
+    let mod_op = |mod_op_type: &str, mod_op_path: &str, mod_op_value: Vec<String>| ModOp {
+        mod_op_type: mod_op_type.to_string(),
+        mod_op_path: mod_op_path.to_string(),
+        mod_op_value,
+        guids: Vec::new(),
+        names: Vec::new(),
+        condition: None,
+        allow_no_match: false,
+    };
 
     match &mod_ops_structure.kind {
-        ModOpsKind::ReplaceValue(value) => mod_ops.push(ModOp {
-            mod_op_type: "Replace".to_string(),
-            mod_op_path: format!("{}/{}", mod_op_root_path, mod_ops_structure.name),
-            mod_op_value: format!("<{0}>{1}</{0}>", mod_ops_structure.name, value),
-        }),
-        ModOpsKind::AddValue(value) => mod_ops.push(ModOp {
-            mod_op_type: "Add".to_string(),
-            mod_op_path: mod_op_root_path.clone(),
-            mod_op_value: format!("<{0}>{1}</{0}>", mod_ops_structure.name, value),
-        }),
-        ModOpsKind::AddNode => mod_ops.push(ModOp {
-            mod_op_type: "Add".to_string(),
-            mod_op_path: mod_op_root_path.clone(),
-            mod_op_value: format!("<{0}></{0}>", mod_ops_structure.name),
-        }),
+        ModOpsKind::ReplaceValue(value) => mod_ops.push(mod_op(
+            "Replace",
+            &path,
+            vec![format!("<{0}>{1}</{0}>", name, value)],
+        )),
+        ModOpsKind::AddValue(value) => mod_ops.push(mod_op(
+            add_type,
+            &add_path,
+            vec![format!("<{0}>{1}</{0}>", name, value)],
+        )),
+        ModOpsKind::AddNode => mod_ops.push(mod_op(
+            add_type,
+            &add_path,
+            vec![format!("<{0}></{0}>", name)],
+        )),
+        // The copy of the list replaces the inherited one, its items need no ModOps of their own.
+        ModOpsKind::AddList(value) => {
+            mod_ops.push(mod_op(
+                add_type,
+                &add_path,
+                vec![format!("<{0}>{1}</{0}>", name, value)],
+            ));
+            return mod_ops;
+        }
+        ModOpsKind::Remove => mod_ops.push(mod_op("Remove", &path, Vec::new())),
         ModOpsKind::None => (),
     }
 
-    mod_ops_structure.children.iter().for_each(|child| {
-        mod_ops.extend(convert_mod_ops_structure_to_mod_ops(
-            format!("{}/{}", mod_op_root_path, mod_ops_structure.name),
-            child,
-        ))
-    });
+    // Several values changing in the same node are set at once with a merge, which adds the
+    // values that are missing and replaces the others.
+    let merged_values = mod_ops_structure
+        .children
+        .iter()
+        .filter_map(|child| match (&child.kind, &child.placement) {
+            (ModOpsKind::ReplaceValue(value) | ModOpsKind::AddValue(value), None) => {
+                Some(format!("<{0}>{1}</{0}>", child.name, value))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let is_merge_required = merged_values.len() > 1;
+    let is_merged = |child: &ModOpsStructure| {
+        is_merge_required
+            && child.placement.is_none()
+            && matches!(
+                child.kind,
+                ModOpsKind::ReplaceValue(_) | ModOpsKind::AddValue(_)
+            )
+    };
+    if is_merge_required {
+        mod_ops.push(mod_op("Merge", &path, merged_values));
+    }
+
+    mod_ops_structure
+        .children
+        .iter()
+        .filter(|child| !is_merged(child))
+        .for_each(|child| {
+            mod_ops.extend(convert_mod_ops_structure_to_mod_ops(path.clone(), child))
+        });
 
     mod_ops
 }
 
-#[derive(Debug)]
-struct ModOp {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ModOp {
     mod_op_type: String,
     mod_op_path: String,
-    mod_op_value: String,
+    /// The lines of XML the ModOp contains, empty for a removal.
+    mod_op_value: Vec<String>,
+    /// The assets the path is relative to, empty if the path addresses the whole file.
+    guids: Vec<String>,
+    /// The names of the assets, written in a comment above the ModOp.
+    names: Vec<String>,
+    /// An XPath that has to match for the ModOp to be applied.
+    condition: Option<String>,
+    allow_no_match: bool,
 }
 
 impl ModOp {
     fn to_xml(&self) -> Vec<String> {
+        let mut attributes = match self.guids.is_empty() {
+            true => format!(
+                "Type=\"{0}\" Path=\"{1}\"",
+                self.mod_op_type, self.mod_op_path
+            ),
+            false => format!(
+                "Type=\"{0}\" GUID=\"{1}\" Path=\"{2}\"",
+                self.mod_op_type,
+                self.guids.join(","),
+                self.mod_op_path
+            ),
+        };
+        if let Some(condition) = &self.condition {
+            attributes.push_str(&format!(" Condition=\"{}\"", condition));
+        }
+        if self.allow_no_match {
+            attributes.push_str(" AllowNoMatch=\"1\"");
+        }
         let mut xml = Vec::new();
-        xml.push(format!(
-            "<ModOp Type=\"{0}\" Path=\"{1}\">",
-            self.mod_op_type, self.mod_op_path
-        ));
-        xml.push(format!("  {}", self.mod_op_value));
+        if !self.names.is_empty() {
+            // A comment can not contain `--`.
+            let mut names = self.names.join(", ");
+            while names.contains("--") {
+                names = names.replace("--", "- -");
+            }
+            xml.push(format!("<!-- {names} -->"));
+        }
+        if self.mod_op_value.is_empty() {
+            xml.push(format!("<ModOp {attributes} />"));
+            return xml;
+        }
+
+        xml.push(format!("<ModOp {attributes}>"));
+        self.mod_op_value
+            .iter()
+            .for_each(|line| xml.push(format!("  {}", line)));
         xml.push("</ModOp>".to_string());
         xml
     }
 }
 
 #[derive(PartialEq, Debug)]
-struct ModOpsStructure {
-    name: String,
-    kind: ModOpsKind,
-    children: Vec<ModOpsStructure>,
+pub(crate) struct ModOpsStructure {
+    pub(crate) name: String,
+    pub(crate) kind: ModOpsKind,
+    placement: Option<Placement>,
+    pub(crate) children: Vec<ModOpsStructure>,
 }
 
-#[derive(PartialEq, Debug)]
-enum ModOpsKind {
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) enum ModOpsKind {
     ReplaceValue(String),
     AddValue(String),
     AddNode,
+    /// The node is added with a copy of the list it inherits, holding the values the entry
+    /// should have.
+    AddList(String),
+    Remove,
     None,
 }
 
 fn are_any_changes_required(mod_ops: &ModOpsStructure) -> bool {
-    let are_changes_required_for_children = mod_ops
-        .children
-        .iter()
-        .any(|child| are_any_changes_required(child));
+    let are_changes_required_for_children = mod_ops.children.iter().any(are_any_changes_required);
 
     match mod_ops.kind {
-        ModOpsKind::ReplaceValue(_) | ModOpsKind::AddValue(_) | ModOpsKind::AddNode => true,
+        ModOpsKind::ReplaceValue(_)
+        | ModOpsKind::AddValue(_)
+        | ModOpsKind::AddNode
+        | ModOpsKind::AddList(_)
+        | ModOpsKind::Remove => true,
         ModOpsKind::None => are_changes_required_for_children,
     }
 }
 
-fn create_mod_ops_structure(content: &XmlNode, state: &State) -> ModOpsStructure {
-    let (kind, mod_ops) = match &content.data {
-        XmlNodeData::Branch(children) => {
+pub(crate) fn create_mod_ops_structure(
+    content: &XmlNode,
+    query: &XmlTag,
+    state: &State,
+) -> Result<ModOpsStructure, String> {
+    let (kind, mod_ops) = match (&content.data, &query.content) {
+        (XmlNodeData::Branch(children), Content::Branch(query_children)) => {
             let child_mod_ops = children
                 .iter()
-                .map(|child| create_mod_ops_structure(&child, state))
-                .filter(|mod_ops| are_any_changes_required(&mod_ops))
+                .map(|child| {
+                    let query_child = query_children
+                        .iter()
+                        .find(|query_child| query_child.matches(&child.name))
+                        .unwrap();
+                    create_mod_ops_structure(child, query_child, state)
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(are_any_changes_required)
                 .collect::<Vec<_>>();
-            match content.present {
-                true => (ModOpsKind::None, child_mod_ops),
-                false => match child_mod_ops.is_empty() {
+            match (content.present, &content.list) {
+                (true, _) => (ModOpsKind::None, child_mod_ops),
+                // Only an entry defining a list can change its items, an entry that has to set
+                // the items it inherits gets a copy of the whole list.
+                (false, Some(list)) => {
+                    let (copy, child_mod_ops) = match state {
+                        State::Excluded => (
+                            Some(list.clone()),
+                            child_mod_ops.into_iter().map(kept_in_copy).collect(),
+                        ),
+                        // Within the copy the items are the entry's own, they change like the
+                        // items of a list it defines.
+                        State::Forced => {
+                            let mut copy = list.clone();
+                            apply_to_copy(&mut copy, children, query_children)?;
+                            let own = XmlNode {
+                                name: content.name.clone(),
+                                present: true,
+                                data: XmlNodeData::Branch(children.iter().map(as_own).collect()),
+                                list: None,
+                            };
+                            let own_mod_ops = create_mod_ops_structure(&own, query, state)?;
+                            (Some(copy), own_mod_ops.children)
+                        }
+                        _ => (None, child_mod_ops),
+                    };
+                    match copy {
+                        Some(copy)
+                            if !child_mod_ops.is_empty()
+                                || copy.inner_xml() != list.inner_xml() =>
+                        {
+                            (ModOpsKind::AddList(copy.inner_xml()), child_mod_ops)
+                        }
+                        _ => (ModOpsKind::None, Vec::new()),
+                    }
+                }
+                (false, None) => match child_mod_ops.is_empty() {
                     true => (ModOpsKind::None, Vec::new()),
                     false => (ModOpsKind::AddNode, child_mod_ops),
                 },
             }
         }
-        XmlNodeData::Leaf(old_value) => match (state, content.present) {
-            (State::Included, true) => (
-                ModOpsKind::ReplaceValue(new_value(&content.name, old_value)),
-                Vec::new(),
-            ),
-            (State::Included, false) => (ModOpsKind::None, Vec::new()),
-            (State::Excluded, true) => (ModOpsKind::None, Vec::new()),
+        (XmlNodeData::Leaf(old_value), Content::Leaf(transforms)) => {
+            let new_value = || {
+                transform::apply(transforms, old_value)
+                    .map(|value| value.to_string())
+                    .map_err(|error| format!("Could not transform {}: {error}", content.name))
+            };
+            match (state, content.present) {
+                (State::Included, true) => (ModOpsKind::ReplaceValue(new_value()?), Vec::new()),
+                (State::Included, false) => (ModOpsKind::None, Vec::new()),
+                (State::Excluded, true) => (ModOpsKind::None, Vec::new()),
+                (State::Excluded, false) => {
+                    (ModOpsKind::AddValue(old_value.to_string()), Vec::new())
+                }
+                (State::ExcludedByAncestor, true) => (ModOpsKind::None, Vec::new()),
+                (State::ExcludedByAncestor, false) => (ModOpsKind::None, Vec::new()),
+                (State::Forced, true) => (ModOpsKind::ReplaceValue(new_value()?), Vec::new()),
+                (State::Forced, false) => (ModOpsKind::AddValue(new_value()?), Vec::new()),
+                (State::ForcedByAncestor, true) => {
+                    (ModOpsKind::ReplaceValue(new_value()?), Vec::new())
+                }
+                (State::ForcedByAncestor, false) => (ModOpsKind::None, Vec::new()),
+            }
+        }
+        // An inherited node can only be removed where it is defined, an excluded entry gets back
+        // the node its ancestor loses.
+        (XmlNodeData::Raw(old_value), Content::Remove) => match (state, content.present) {
+            (State::Included | State::Forced | State::ForcedByAncestor, true) => {
+                (ModOpsKind::Remove, Vec::new())
+            }
             (State::Excluded, false) => (ModOpsKind::AddValue(old_value.clone()), Vec::new()),
-            (State::ExcludedByAncestor, true) => (ModOpsKind::None, Vec::new()),
-            (State::ExcludedByAncestor, false) => (ModOpsKind::None, Vec::new()),
-            (State::Forced, true) => (
-                ModOpsKind::ReplaceValue(new_value(&content.name, old_value)),
-                Vec::new(),
-            ),
-            (State::Forced, false) => (
-                ModOpsKind::AddValue(new_value(&content.name, old_value)),
-                Vec::new(),
-            ),
-            (State::ForcedByAncestor, true) => (
-                ModOpsKind::ReplaceValue(new_value(&content.name, old_value)),
-                Vec::new(),
-            ),
-            (State::ForcedByAncestor, false) => (ModOpsKind::None, Vec::new()),
+            _ => (ModOpsKind::None, Vec::new()),
         },
-        XmlNodeData::None => (ModOpsKind::None, Vec::new()),
+        _ => (ModOpsKind::None, Vec::new()),
     };
 
-    let mod_ops_structure = ModOpsStructure {
+    Ok(ModOpsStructure {
         name: content.name.clone(),
         kind,
+        placement: query.placement.clone(),
         children: mod_ops,
-    };
-
-    mod_ops_structure
+    })
 }
 
-fn new_value(name: &str, current_value: &str) -> String {
-    match name {
-        "TransporterSpeed" => {
-            let mut value: f64 = current_value.parse().unwrap();
-            value = value * 30.0;
-            value.to_string()
-        }
-        "ResolverMovementSpeed" | "IntensityDecreaseRate" => {
-            let mut value: f64 = current_value.parse().unwrap();
-            value = value * 10.0;
-            value.to_string()
-        }
-        "StorageAmount" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value * 10;
-            value.to_string()
-        }
-        "IndustrializationDistance"
-        | "FullSatisfactionDistance"
-        | "NoSatisfactionDistance"
-        | "HeatRange" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value * 2;
-            value.to_string()
-        }
-        "CycleTime" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value / 5;
-            value.to_string()
-        }
-        "LoadingTime" | "UnloadingTime" => {
-            let mut value: f64 = current_value.parse().unwrap();
-            value = value / 5.0;
-            value = value.ceil();
-            value.to_string()
-        }
-        "CraftingTime" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value / 10;
-            value.to_string()
-        }
-        "MinPauseBetweenEvents" | "MaxPauseBetweenEvents" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value / 5000;
-            value.to_string()
-        }
-        "ResolverUnitCount" => {
-            let mut value: usize = current_value.parse().unwrap();
-            value = value + 1;
-            value.to_string()
-        }
-        "MoveInMs" | "MoveOutMs" | "MoveRandomMs" => String::from("10"),
-        _ => {
-            todo!("new value {} not implemented yet", name);
+/// Sets the new values of the queried nodes in the copy of an inherited list and takes out the
+/// removed ones.
+fn apply_to_copy(
+    element: &mut Element,
+    children: &[XmlNode],
+    query_children: &[XmlTag],
+) -> Result<(), String> {
+    for child in children {
+        let Some(query_child) = query_children
+            .iter()
+            .find(|query_child| query_child.matches(&child.name))
+        else {
+            continue;
+        };
+        let copy_child = element
+            .children
+            .iter_mut()
+            .find(|copy_child| copy_child.matches_step(&child.name));
+        match (&child.data, &query_child.content, copy_child) {
+            (
+                XmlNodeData::Branch(grandchildren),
+                Content::Branch(query_grandchildren),
+                Some(copy_child),
+            ) => apply_to_copy(copy_child, grandchildren, query_grandchildren)?,
+            (XmlNodeData::Leaf(old_value), Content::Leaf(transforms), copy_child) => {
+                let new_value = transform::apply(transforms, old_value)
+                    .map_err(|error| format!("Could not transform {}: {error}", child.name))?
+                    .to_string();
+                match copy_child {
+                    Some(copy_child) => copy_child.text = Some(new_value),
+                    // A value the list inherits from further up.
+                    None => element.children.push(Element {
+                        name: child.name.clone(),
+                        text: Some(new_value),
+                        children: Vec::new(),
+                        added_by: None,
+                        changed_by: Vec::new(),
+                    }),
+                }
+            }
+            (XmlNodeData::Raw(_), Content::Remove, Some(_)) => element
+                .children
+                .retain(|copy_child| !copy_child.matches_step(&child.name)),
+            _ => (),
         }
     }
+    Ok(())
 }
 
-fn create_mod_directory(mod_path: &PathBuf) {
-    match std::fs::create_dir(&mod_path) {
-        Err(error) => match error.kind() {
-            std::io::ErrorKind::AlreadyExists => (),
-            _ => panic!("Error creating {mod_path:?}: {error}"),
+/// The values an excluded entry adds back are kept in the copy of the list instead, they are
+/// replaced with themselves.
+fn kept_in_copy(structure: ModOpsStructure) -> ModOpsStructure {
+    ModOpsStructure {
+        kind: match structure.kind {
+            ModOpsKind::AddValue(value) => ModOpsKind::ReplaceValue(value),
+            ModOpsKind::AddNode => ModOpsKind::None,
+            kind => kind,
         },
-        _ => (),
+        children: structure.children.into_iter().map(kept_in_copy).collect(),
+        ..structure
     }
 }
 
-pub(crate) fn delete_mod_files(mod_path: &PathBuf) {
-    match std::fs::remove_dir_all(mod_path) {
-        Err(error) => match error.kind() {
-            std::io::ErrorKind::NotFound => (),
-            _ => panic!("Error deleting {mod_path:?}: {error}"),
+/// The node as if the entry defined it and all it contains.
+fn as_own(node: &XmlNode) -> XmlNode {
+    XmlNode {
+        name: node.name.clone(),
+        present: true,
+        data: match &node.data {
+            XmlNodeData::Branch(children) => {
+                XmlNodeData::Branch(children.iter().map(as_own).collect())
+            }
+            data => data.clone(),
         },
-        _ => (),
+        list: node.list.clone(),
+    }
+}
+
+/// What the mod does with the node at the path of the query in the entry, the path starts with
+/// the name of the query.
+pub(crate) fn mod_ops_kind_at(
+    mod_entry: &ModEntry,
+    query: &XmlTag,
+    path: &[String],
+) -> Result<ModOpsKind, String> {
+    let structure = create_mod_ops_structure(&mod_entry.content, query, &mod_entry.state)?;
+    let mut structure = &structure;
+    for name in &path[1..] {
+        // Children without changes are left out of the structure.
+        let Some(child) = structure.children.iter().find(|child| child.name == *name) else {
+            return Ok(ModOpsKind::None);
+        };
+        structure = child;
+    }
+    Ok(structure.kind.clone())
+}
+
+fn create_mod_directory(mod_path: &Path) -> Result<(), Error> {
+    match std::fs::create_dir(mod_path) {
+        Err(error) if error.kind() != std::io::ErrorKind::AlreadyExists => Err(Error::Io {
+            path: mod_path.to_path_buf(),
+            source: error,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn delete_mod_files(mod_path: &Path) -> Result<(), Error> {
+    match std::fs::remove_dir_all(mod_path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(Error::Io {
+            path: mod_path.to_path_buf(),
+            source: error,
+        }),
+        _ => Ok(()),
     }
 }
 
 fn enhanced_name(mod_name: &str) -> String {
     format!("JJ's Enhanced {}", mod_name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mod_builder, recipe};
+
+    const PROPERTIES: &str = r#"<Properties><DefaultValues>
+  <Standard><GUID>0</GUID></Standard>
+  <Maintenance><Maintenances /></Maintenance>
+</DefaultValues></Properties>"#;
+
+    const TEMPLATES: &str = r#"<Templates>
+  <Template><Name>FactoryBuilding7</Name><Properties>
+    <Standard /><Maintenance />
+  </Properties></Template>
+</Templates>"#;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>1</GUID></Standard>
+    <Maintenance><Maintenances>
+      <Item><Product>1010017</Product><Amount>10</Amount><InactiveAmount>5</InactiveAmount></Item>
+      <Item><Product>1010018</Product><Amount>20</Amount></Item>
+      <Item><Amount>30</Amount></Item>
+    </Maintenances></Maintenance>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>1</BaseAssetGUID><Values>
+    <Standard><GUID>2</GUID></Standard>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>1</BaseAssetGUID><Values>
+    <Standard><GUID>3</GUID></Standard>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>4</GUID></Standard>
+    <Maintenance><Maintenances>
+      <Item><Product>1010017</Product><Amount>10</Amount></Item>
+      <Item><Product> 1010017 </Product><Amount>10</Amount></Item>
+    </Maintenances></Maintenance>
+  </Values></Asset>
+</AssetList>"#;
+
+    fn database() -> AssetDatabase {
+        AssetDatabase::from_documents(PROPERTIES, TEMPLATES, ASSETS, &[])
+    }
+
+    /// The ModOps the recipe creates for the assets and the problems found.
+    fn mod_ops(database: &AssetDatabase, recipe: &str) -> (Vec<String>, Vec<String>) {
+        let recipes = recipe::parse_recipes("test", recipe).unwrap();
+        let (mod_entries, errors) = mod_builder::build(&recipes[0], database);
+        let (mod_files, mod_ops_errors) = create_mod_ops(
+            &recipes[0],
+            &mod_entries,
+            database,
+            ModOpStyle::Guid,
+            OptionalSources::None,
+        );
+        let mod_ops = mod_files
+            .path_vs_mod_ops
+            .values()
+            .flatten()
+            .flat_map(ModOp::to_xml)
+            .collect();
+        let errors = errors
+            .into_iter()
+            .chain(mod_ops_errors)
+            .map(|error| match error {
+                Error::Data { xpath, message, .. } => format!("{xpath}: {message}"),
+                error => format!("{error:?}"),
+            })
+            .collect();
+        (mod_ops, errors)
+    }
+
+    #[test]
+    fn changes_every_item_having_the_key() {
+        let database = database();
+        let (mod_ops, errors) = mod_ops(
+            &database,
+            r#"[[mod]]
+name = "Maintenance"
+targets = [{ path = "Maintenance/Maintenances/Item[Product=*]/Amount", transform = [{ set = 1 }] }]
+"#,
+        );
+        assert_eq!(
+            mod_ops,
+            [
+                r#"<ModOp Type="Replace" GUID="1" Path="/Values/Maintenance/Maintenances/Item[Product='1010017']/Amount">"#,
+                "  <Amount>1</Amount>",
+                "</ModOp>",
+                r#"<ModOp Type="Replace" GUID="1" Path="/Values/Maintenance/Maintenances/Item[Product='1010018']/Amount">"#,
+                "  <Amount>1</Amount>",
+                "</ModOp>",
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+
+        // The game data with the ModOps applied, the item without a key is left as it is.
+        let xml = roxmltree::Document::parse(ASSETS).unwrap();
+        let mut documents = [Element::from_node(&xml.root_element())];
+        let mod_ops = format!("<ModOps>{}</ModOps>", mod_ops.join(""));
+        let (_, patch_errors) = crate::patch::apply_mod_ops(
+            &mut documents,
+            Path::new("mod"),
+            Path::new(""),
+            &mod_ops,
+            None,
+        );
+        assert!(patch_errors.is_empty(), "{patch_errors:?}");
+        assert_eq!(
+            documents[0].children[0].children[1].children[1].inner_xml(),
+            "<Maintenances><Item><Product>1010017</Product><Amount>1</Amount>\
+             <InactiveAmount>5</InactiveAmount></Item><Item><Product>1010018</Product>\
+             <Amount>1</Amount></Item><Item><Amount>30</Amount></Item></Maintenances>"
+        );
+    }
+
+    #[test]
+    fn changes_the_items_selected_by_their_key() {
+        let (mod_ops, errors) = mod_ops(
+            &database(),
+            r#"[[mod]]
+name = "Maintenance"
+targets = [
+    { path = "Maintenance/Maintenances/Item[Product=1010017]/Amount", transform = [{ divide = 2 }] },
+    { path = "Maintenance/Maintenances/Item[Product=1010018]", action = "remove" },
+]
+excluded_guids = ["3"]
+"#,
+        );
+        assert_eq!(
+            mod_ops,
+            [
+                r#"<ModOp Type="Replace" GUID="1" Path="/Values/Maintenance/Maintenances/Item[Product='1010017']/Amount">"#,
+                "  <Amount>5</Amount>",
+                "</ModOp>",
+                r#"<ModOp Type="Remove" GUID="1" Path="/Values/Maintenance/Maintenances/Item[Product='1010018']" />"#,
+                r#"<ModOp Type="Add" GUID="3" Path="/Values">"#,
+                "  <Maintenance></Maintenance>",
+                "</ModOp>",
+                r#"<ModOp Type="Add" GUID="3" Path="/Values/Maintenance">"#,
+                "  <Maintenances><Item><Product>1010017</Product><Amount>10</Amount>\
+                 <InactiveAmount>5</InactiveAmount></Item><Item><Product>1010018</Product>\
+                 <Amount>20</Amount></Item><Item><Amount>30</Amount></Item></Maintenances>",
+                "</ModOp>",
+            ]
+        );
+        assert_eq!(
+            errors,
+            [
+                "/AssetList[1]/Asset[4]/Values/Maintenance/Maintenances: More than one \
+              Item[Product='1010017'] node found"
+            ]
+        );
+    }
+}
@@ -1,20 +1,23 @@
-use std::path::PathBuf;
+use crate::data_path::DataPath;
+use serde::{Deserialize, Serialize};
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Identifier {
-    pub(crate) file_path: PathBuf,
+    pub(crate) file_path: DataPath,
     pub(crate) kind: Kind,
     pub(crate) value: String,
 }
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum Kind {
     XPath,
     Name,
     GUID,
 }
 
-#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum ParentIdentifier {
     None,
     DefaultValues,
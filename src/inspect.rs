@@ -0,0 +1,218 @@
+use crate::{
+    database::{AssetDatabase, Entry, NodeType},
+    element::Element,
+    error::Error,
+    helper::{self, ModOpsKind},
+    identifier::Kind,
+    mod_builder,
+    recipe::Recipe,
+    xml_node::{XmlNode, XmlNodeData},
+    xml_structure::{Content, XmlTag},
+};
+use itertools::Itertools;
+use std::fmt::Write;
+
+/// Describes the asset with the GUID or the template with the name: where it is defined, what it
+/// inherits from and, for every value a recipe queries, the value at each level of the
+/// inheritance together with the value the game uses once the recipe's mod is applied.
+pub(crate) fn inspect(
+    database: &AssetDatabase,
+    recipes: &[Recipe],
+    name: &str,
+) -> Result<String, Error> {
+    let index = database.find(name).ok_or_else(|| Error::UnknownEntry {
+        name: name.to_string(),
+    })?;
+    let entry = &database.entries[index];
+    let ancestors = database.ancestors(index);
+
+    let mut text = String::new();
+    let title = match entry.node_type {
+        NodeType::Asset => format!("Asset {}", label(entry)),
+        _ => format!("Template {}", label(entry)),
+    };
+    let _ = writeln!(text, "{title}");
+    if let Some(name) = database.name(entry) {
+        let _ = writeln!(text, "  Name: {name}");
+    }
+    if let Some(template) = ancestors
+        .iter()
+        .map(|&index| &database.entries[index])
+        .find(|ancestor| ancestor.node_type == NodeType::Template)
+    {
+        let _ = writeln!(text, "  Template: {}", template.identifier.value);
+    }
+    let _ = writeln!(
+        text,
+        "  Defined in: {} {}",
+        entry.identifier.file_path, entry.xpath
+    );
+    let _ = writeln!(text, "  Source: {}", entry.origin.source);
+    if !entry.origin.changed_by.is_empty() {
+        let changed_by = entry.origin.changed_by.iter().cloned().collect::<Vec<_>>();
+        let _ = writeln!(text, "  Changed by: {}", changed_by.join(", "));
+    }
+    let parents = ancestors
+        .iter()
+        .map(|&index| named_label(database, &database.entries[index]))
+        .chain(["DefaultValues".to_string()])
+        .collect::<Vec<_>>();
+    let _ = writeln!(text, "  Inherits from: {}", parents.join(" -> "));
+
+    for recipe in recipes {
+        let (mod_entries, _) = mod_builder::build(recipe, database);
+        let mod_entry_of = |index: usize| {
+            mod_entries
+                .iter()
+                .find(|mod_entry| std::ptr::eq(mod_entry.entry, &database.entries[index]))
+        };
+        let Some(mod_entry) = mod_entry_of(index) else {
+            continue;
+        };
+
+        // The levels the value can come from, the entry itself first. Templates inherit from
+        // the last DefaultValues having the query.
+        let default_values = mod_entries
+            .iter()
+            .rev()
+            .find(|mod_entry| mod_entry.entry.node_type == NodeType::DefaultValues);
+        let levels = [index]
+            .into_iter()
+            .chain(ancestors.iter().copied())
+            .map(|index| (&database.entries[index], mod_entry_of(index)))
+            .chain(default_values.map(|mod_entry| (mod_entry.entry, Some(mod_entry))))
+            .map(|(entry, mod_entry)| (label(entry), entry, mod_entry))
+            .collect::<Vec<_>>();
+        let width = levels
+            .iter()
+            .map(|(label, ..)| label.len())
+            .chain(["Result".len()])
+            .max()
+            .unwrap_or_default();
+
+        for (path, is_removed) in
+            query_leaves(&recipe.query, &recipe.query.name, Some(&mod_entry.content))
+        {
+            let _ = writeln!(text);
+            let _ = writeln!(text, "{}: {}", recipe.name, path.join("/"));
+
+            // The result is inherited from the top, each level keeps it unless the mod or the
+            // level itself sets the value.
+            let mut result: Result<Option<String>, String> = Ok(None);
+            let mut rows = Vec::new();
+            for (label, level_entry, level_mod_entry) in levels.iter().rev() {
+                let value = level_entry
+                    .properties
+                    .as_ref()
+                    .and_then(|properties| find_value(properties, &path, is_removed));
+                rows.push((label.clone(), display(&value)));
+                result = result.and_then(|inherited| {
+                    let kind = match level_mod_entry {
+                        Some(level) => helper::mod_ops_kind_at(level, &recipe.query, &path)?,
+                        None => ModOpsKind::None,
+                    };
+                    Ok(match kind {
+                        ModOpsKind::ReplaceValue(new) | ModOpsKind::AddValue(new) => {
+                            match is_removed {
+                                true => Some("present".to_string()),
+                                false => Some(new),
+                            }
+                        }
+                        ModOpsKind::Remove => None,
+                        ModOpsKind::AddNode | ModOpsKind::AddList(_) | ModOpsKind::None => {
+                            value.or(inherited)
+                        }
+                    })
+                });
+            }
+            rows.reverse();
+
+            rows.iter().for_each(|(label, value)| {
+                let _ = writeln!(text, "  {label:<width$}  {value}");
+            });
+            let result = match result {
+                Ok(result) => format!("{} ({})", display(&result), mod_entry.state),
+                Err(message) => message,
+            };
+            let _ = writeln!(text, "  {:<width$}  {result}", "Result");
+        }
+    }
+
+    Ok(text)
+}
+
+/// The GUID of an asset, the name of a template, or the XPath of an asset without a GUID.
+pub(crate) fn label(entry: &Entry) -> String {
+    match (entry.node_type, &entry.identifier.kind) {
+        (NodeType::DefaultValues, _) => "DefaultValues".to_string(),
+        (_, Kind::XPath) => format!("{} {}", entry.identifier.file_path, entry.identifier.value),
+        _ => entry.identifier.value.clone(),
+    }
+}
+
+/// The label followed by the name of the asset, if it has one.
+pub(crate) fn named_label(database: &AssetDatabase, entry: &Entry) -> String {
+    match database.name(entry) {
+        Some(name) => format!("{} ({name})", label(entry)),
+        None => label(entry),
+    }
+}
+
+/// The paths of the query's leaves and removed nodes, with whether the node is removed. The items
+/// of lists are the ones the content of the entry has.
+fn query_leaves(query: &XmlTag, name: &str, content: Option<&XmlNode>) -> Vec<(Vec<String>, bool)> {
+    match &query.content {
+        Content::Branch(children) => {
+            let content_children = match content.map(|content| &content.data) {
+                Some(XmlNodeData::Branch(content_children)) => content_children.as_slice(),
+                _ => &[],
+            };
+            children
+                .iter()
+                .flat_map(|child| match &child.key {
+                    None => query_leaves(
+                        child,
+                        &child.name,
+                        content_children
+                            .iter()
+                            .find(|content_child| content_child.name == child.name),
+                    ),
+                    Some(_) => content_children
+                        .iter()
+                        .filter(|content_child| child.matches(&content_child.name))
+                        .flat_map(|content_child| {
+                            query_leaves(child, &content_child.name, Some(content_child))
+                        })
+                        .collect(),
+                })
+                .map(|(mut path, is_removed)| {
+                    path.insert(0, name.to_string());
+                    (path, is_removed)
+                })
+                .collect()
+        }
+        Content::Leaf(_) => vec![(vec![name.to_string()], false)],
+        Content::Remove => vec![(vec![name.to_string()], true)],
+    }
+}
+
+/// The value the properties set at the path, `present` for a node that is removed as a whole.
+fn find_value(properties: &Element, path: &[String], is_removed: bool) -> Option<String> {
+    let element = path.iter().try_fold(properties, |element, step| {
+        element
+            .children
+            .iter()
+            .filter(|child| child.matches_step(step))
+            .at_most_one()
+            .ok()
+            .flatten()
+    })?;
+    match is_removed {
+        true => Some("present".to_string()),
+        false => Some(element.text.clone().unwrap_or_default()),
+    }
+}
+
+fn display(value: &Option<String>) -> String {
+    value.clone().unwrap_or_else(|| "-".to_string())
+}
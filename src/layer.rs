@@ -0,0 +1,195 @@
+use crate::{
+    data_path::DataPath, error::Error, modinfo::InputModInfo, rda::Archive, source::Source,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const BASE_GAME_DIRECTORY: &str = "maindata";
+
+/// A subdirectory of the input directory, the base game or a mod. The files of a layer override
+/// the ones of the layers loaded before it.
+#[derive(Debug)]
+pub(crate) struct Layer {
+    pub(crate) path: PathBuf,
+    /// The ModID of a mod, taken from its `modinfo.json` or its directory name, `None` for the
+    /// base game.
+    pub(crate) mod_id: Option<String>,
+    load_after_ids: Vec<String>,
+}
+
+impl Layer {
+    /// The source of a file of the layer, `data/dlc01/...` comes from the DLC `dlc01`.
+    pub(crate) fn source_of(&self, data_path: &DataPath) -> Source {
+        if let Some(mod_id) = &self.mod_id {
+            return Source::Mod(mod_id.clone());
+        }
+
+        let mut parts = data_path.components();
+        match (parts.next(), parts.next()) {
+            (Some("data"), Some(dlc)) if dlc.starts_with("dlc") => Source::Dlc(dlc.to_string()),
+            _ => Source::BaseGame,
+        }
+    }
+}
+
+/// A data file of a layer, on disk or in one of the layer's `.rda` archives.
+#[derive(Debug, Clone)]
+pub(crate) struct DataFile {
+    /// The path shown in messages, for an archived file the archive's path followed by the path
+    /// inside it.
+    pub(crate) path: PathBuf,
+    pub(crate) data_path: DataPath,
+    archive: Option<(Arc<Archive>, usize)>,
+}
+
+impl DataFile {
+    pub(crate) fn on_disk(layer: &Layer, path: PathBuf) -> DataFile {
+        DataFile {
+            data_path: DataPath::from_layer_file(&layer.path, &path),
+            path,
+            archive: None,
+        }
+    }
+
+    pub(crate) fn archived(archive: &Arc<Archive>, index: usize) -> DataFile {
+        let name = &archive.files[index].name;
+        DataFile {
+            path: archive.path.join(name),
+            data_path: DataPath::from_archived_file(name),
+            archive: Some((archive.clone(), index)),
+        }
+    }
+
+    pub(crate) fn read_to_string(&self) -> Result<String, Error> {
+        match &self.archive {
+            Some((archive, index)) => {
+                String::from_utf8(archive.read(*index)?).map_err(|_| Error::Archive {
+                    path: self.path.clone(),
+                    message: "File is not valid UTF-8".to_string(),
+                })
+            }
+            None => std::fs::read_to_string(&self.path).map_err(|source| Error::Io {
+                path: self.path.clone(),
+                source,
+            }),
+        }
+    }
+}
+
+/// Finds the layers of the input directory in load order: the base game first, then the mods by
+/// directory name, moved behind the mods listed in their `LoadAfterIds`.
+pub(crate) fn load_layers(input_path: &Path) -> (Vec<Layer>, Vec<Error>) {
+    let mut errors = Vec::new();
+
+    let mut directories = match std::fs::read_dir(input_path) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>(),
+        Err(source) => {
+            errors.push(Error::Io {
+                path: input_path.to_path_buf(),
+                source,
+            });
+            Vec::new()
+        }
+    };
+    directories.sort();
+
+    let mut base_game = Vec::new();
+    let mut mods = Vec::new();
+    for path in directories {
+        let directory = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if directory == BASE_GAME_DIRECTORY {
+            base_game.push(Layer {
+                path,
+                mod_id: None,
+                load_after_ids: Vec::new(),
+            });
+            continue;
+        }
+
+        let modinfo = match read_modinfo(&path) {
+            Ok(modinfo) => modinfo.unwrap_or_default(),
+            Err(error) => {
+                errors.push(error);
+                InputModInfo::default()
+            }
+        };
+        mods.push(Layer {
+            path,
+            mod_id: Some(modinfo.mod_id.unwrap_or(directory)),
+            load_after_ids: modinfo.load_after_ids,
+        });
+    }
+
+    let (mods, cycle) = sort_by_load_after_ids(mods);
+    if !cycle.is_empty() {
+        errors.push(Error::LoadOrder { mod_ids: cycle });
+    }
+
+    base_game.extend(mods);
+    (base_game, errors)
+}
+
+fn read_modinfo(path: &Path) -> Result<Option<InputModInfo>, Error> {
+    let modinfo_path = path.join("modinfo.json");
+    if !modinfo_path.is_file() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&modinfo_path).map_err(|source| Error::Io {
+        path: modinfo_path.clone(),
+        source,
+    })?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|source| Error::Json {
+            path: modinfo_path,
+            source,
+        })
+}
+
+/// Keeps the given order as far as possible, a mod only moves behind the mods it loads after.
+/// The ModIDs of mods that wait for each other, and of the mods waiting for those, are returned
+/// alongside, they keep their order.
+fn sort_by_load_after_ids(mut remaining: Vec<Layer>) -> (Vec<Layer>, Vec<String>) {
+    let mod_ids = remaining
+        .iter()
+        .filter_map(|layer| layer.mod_id.clone())
+        .collect::<HashSet<_>>();
+    let mut loaded = HashSet::new();
+    let mut sorted = Vec::new();
+    let mut cycle = Vec::new();
+
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|layer| {
+            layer
+                .load_after_ids
+                .iter()
+                .filter(|id| mod_ids.contains(*id) && Some(*id) != layer.mod_id.as_ref())
+                .all(|id| loaded.contains(id))
+        });
+        let Some(index) = next else {
+            cycle = remaining
+                .iter()
+                .filter_map(|layer| layer.mod_id.clone())
+                .collect();
+            sorted.append(&mut remaining);
+            break;
+        };
+        let layer = remaining.remove(index);
+        if let Some(mod_id) = &layer.mod_id {
+            loaded.insert(mod_id.clone());
+        }
+        sorted.push(layer);
+    }
+
+    (sorted, cycle)
+}
@@ -0,0 +1,387 @@
+use crate::{
+    database::{AssetDatabase, Entry, NodeType},
+    error::Error,
+    helper,
+    identifier::ParentIdentifier,
+    recipe::Recipe,
+    selector::Selector,
+    state::{Reason, State},
+    xml_node::XmlNode,
+};
+
+/// An entry of the database that is part of a mod, with the state and the effective content
+/// of the recipe's query.
+#[derive(Debug)]
+pub(crate) struct ModEntry<'a> {
+    pub(crate) entry: &'a Entry,
+    pub(crate) state: State,
+    /// The rule the state comes from.
+    pub(crate) reason: Reason,
+    pub(crate) content: XmlNode,
+}
+
+#[derive(Debug, Clone)]
+enum Resolution {
+    Unvisited,
+    InProgress,
+    Skipped,
+    Resolved(State, Reason, XmlNode),
+}
+
+/// Collects the entries of the database that are part of the recipe's mod, in database order.
+///
+/// Entries that cannot be resolved are left out, the problems are returned alongside.
+pub(crate) fn build<'a>(
+    recipe: &Recipe,
+    database: &'a AssetDatabase,
+) -> (Vec<ModEntry<'a>>, Vec<Error>) {
+    let mut builder = ModBuilder {
+        recipe,
+        database,
+        default_values: None,
+        resolutions: vec![Resolution::Unvisited; database.entries.len()],
+        errors: Vec::new(),
+    };
+
+    (0..database.entries.len()).for_each(|index| builder.resolve(index));
+
+    let mod_entries = builder
+        .resolutions
+        .into_iter()
+        .zip(&database.entries)
+        .filter_map(|(resolution, entry)| match resolution {
+            Resolution::Resolved(state, reason, content) => Some(ModEntry {
+                entry,
+                state,
+                reason,
+                content,
+            }),
+            _ => None,
+        })
+        .collect();
+    (mod_entries, builder.errors)
+}
+
+struct ModBuilder<'a> {
+    recipe: &'a Recipe,
+    database: &'a AssetDatabase,
+    /// The last DefaultValues entry containing the query, which the templates inherit from.
+    default_values: Option<usize>,
+    resolutions: Vec<Resolution>,
+    errors: Vec<Error>,
+}
+
+impl ModBuilder<'_> {
+    fn resolve(&mut self, index: usize) {
+        if !matches!(self.resolutions[index], Resolution::Unvisited) {
+            return;
+        }
+        self.resolutions[index] = Resolution::InProgress;
+
+        let entry = &self.database.entries[index];
+        let resolution = match entry.node_type {
+            NodeType::DefaultValues => self.resolve_default_values(entry),
+            NodeType::Template => self.resolve_template(entry),
+            NodeType::Asset => self.resolve_asset(index),
+        };
+
+        if entry.node_type == NodeType::DefaultValues
+            && matches!(resolution, Resolution::Resolved(..))
+        {
+            self.default_values = Some(index);
+        }
+        self.resolutions[index] = resolution;
+    }
+
+    fn resolve_default_values(&mut self, entry: &Entry) -> Resolution {
+        let query = &self.recipe.query;
+        let Some(properties) = &entry.properties else {
+            return Resolution::Skipped;
+        };
+        if !helper::has_direct_child(properties, &query.name) {
+            return Resolution::Skipped;
+        }
+
+        match helper::extract_content(
+            properties,
+            query,
+            None,
+            &entry.identifier.file_path,
+            &entry.xpath,
+        ) {
+            Ok(Some(content)) => Resolution::Resolved(State::Included, Reason::NotListed, content),
+            Ok(None) => Resolution::Skipped,
+            Err(error) => self.skip(error),
+        }
+    }
+
+    fn resolve_template(&mut self, entry: &Entry) -> Resolution {
+        let query = &self.recipe.query;
+        let Some(properties) = &entry.properties else {
+            return Resolution::Skipped;
+        };
+        if !helper::has_direct_child(properties, &query.name) {
+            return Resolution::Skipped;
+        }
+
+        // Without DefaultValues the values a template does not set are unknown, the first such
+        // template reports it for the whole recipe.
+        let Some(default_values) = self.default_values else {
+            if !self
+                .errors
+                .iter()
+                .any(|error| matches!(error, Error::MissingDefaultValues { .. }))
+            {
+                self.errors.push(Error::MissingDefaultValues {
+                    query: query.name.clone(),
+                });
+            }
+            return Resolution::Skipped;
+        };
+        let Resolution::Resolved(_, _, parent_content) = &self.resolutions[default_values] else {
+            return Resolution::Skipped;
+        };
+        let content = match helper::extract_content(
+            properties,
+            query,
+            Some(parent_content),
+            &entry.identifier.file_path,
+            &format!("{}/Properties", entry.xpath),
+        ) {
+            Ok(Some(content)) => content,
+            Ok(None) => return Resolution::Skipped,
+            Err(error) => return self.skip(error),
+        };
+
+        let (state, reason) = match self
+            .recipe
+            .excluded_templates
+            .contains(&entry.identifier.value)
+        {
+            true => (State::Excluded, Reason::ExcludedTemplate),
+            false => (State::Included, Reason::NotListed),
+        };
+        Resolution::Resolved(state, reason, content)
+    }
+
+    fn resolve_asset(&mut self, index: usize) -> Resolution {
+        let entry = &self.database.entries[index];
+        let parent = match &entry.parent {
+            ParentIdentifier::Template(_) | ParentIdentifier::GUID(_) => &entry.parent,
+            _ => return Resolution::Skipped,
+        };
+        let Some(parent_index) = self.database.parent_index(parent) else {
+            return Resolution::Skipped;
+        };
+
+        // Walk up first, the parent's content holds its effective values, inherited through
+        // DefaultValues -> Template -> BaseAssetGUID/ScenarioBaseAssetGUID, so absent leaves of
+        // this asset resolve to the value the game would actually use.
+        self.resolve(parent_index);
+        let Resolution::Resolved(parent_state, _, parent_content) = &self.resolutions[parent_index]
+        else {
+            return Resolution::Skipped;
+        };
+
+        // Listed GUIDs come first, then the selectors, forcing before excluding.
+        let value = &entry.identifier.value;
+        let matches = |selector: &Option<Selector>| {
+            selector
+                .as_ref()
+                .is_some_and(|selector| selector.matches(self.database, index))
+        };
+        let (state, reason) = if self.recipe.forced_guids.contains(value) {
+            (State::Forced, Reason::ForcedGuid)
+        } else if self.recipe.excluded_guids.contains(value) {
+            (State::Excluded, Reason::ExcludedGuid)
+        } else if matches(&self.recipe.forced_where) {
+            (State::Forced, Reason::ForcedSelector)
+        } else if matches(&self.recipe.excluded_where) {
+            (State::Excluded, Reason::ExcludedSelector)
+        } else {
+            let state = match parent_state {
+                State::Included => State::Included,
+                State::Excluded | State::ExcludedByAncestor => State::ExcludedByAncestor,
+                State::Forced | State::ForcedByAncestor => State::ForcedByAncestor,
+            };
+            (state, Reason::Inherited)
+        };
+
+        let Some(properties) = entry.properties.as_ref() else {
+            return self.skip(Error::Data {
+                file: entry.identifier.file_path.clone(),
+                xpath: entry.xpath.clone(),
+                message: "No Values node found".to_string(),
+            });
+        };
+        match helper::extract_content(
+            properties,
+            &self.recipe.query,
+            Some(parent_content),
+            &entry.identifier.file_path,
+            &format!("{}/Values", entry.xpath),
+        ) {
+            Ok(Some(content)) => Resolution::Resolved(state, reason, content),
+            Ok(None) => Resolution::Skipped,
+            Err(error) => self.skip(error),
+        }
+    }
+
+    fn skip(&mut self, error: Error) -> Resolution {
+        self.errors.push(error);
+        Resolution::Skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{recipe, xml_node::XmlNodeData};
+
+    const PROPERTIES: &str = r#"<Properties><DefaultValues>
+  <Standard><GUID>0</GUID></Standard>
+  <FactoryBase><CycleTime>30</CycleTime></FactoryBase>
+</DefaultValues></Properties>"#;
+
+    const TEMPLATES: &str = r#"<Templates>
+  <Template><Name>FactoryBuilding7</Name><Properties>
+    <Standard /><FactoryBase><CycleTime>60</CycleTime></FactoryBase>
+  </Properties></Template>
+</Templates>"#;
+
+    /// The GUID, state, reason and effective cycle time of the assets of the mod, for a recipe
+    /// changing the cycle time with the lists given in TOML.
+    fn build_assets(assets: &str, lists: &str) -> Vec<(String, State, Reason, String)> {
+        let database = AssetDatabase::from_documents(PROPERTIES, TEMPLATES, assets, &[]);
+        let text = format!(
+            r#"[[mod]]
+name = "Cycle"
+query = {{ name = "FactoryBase", children = [{{ name = "CycleTime", transform = [{{ multiply = 2 }}] }}] }}
+{lists}
+"#
+        );
+        let recipes = recipe::parse_recipes("test", &text).unwrap();
+        let (mod_entries, errors) = build(&recipes[0], &database);
+        assert!(errors.is_empty(), "{errors:?}");
+        mod_entries
+            .into_iter()
+            .filter(|mod_entry| mod_entry.entry.node_type == NodeType::Asset)
+            .map(|mod_entry| {
+                let XmlNodeData::Branch(children) = &mod_entry.content.data else {
+                    panic!("{:?} is no branch", mod_entry.content);
+                };
+                let XmlNodeData::Leaf(cycle_time) = &children[0].data else {
+                    panic!("{:?} is no leaf", children[0]);
+                };
+                (
+                    mod_entry.entry.identifier.value.clone(),
+                    mod_entry.state,
+                    mod_entry.reason,
+                    cycle_time.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn absent_values_come_from_the_nearest_parent_having_them() {
+        let assets = r#"<AssetList>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>1</GUID></Standard>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>2</GUID></Standard>
+    <FactoryBase><CycleTime>45</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>2</BaseAssetGUID><Values>
+    <Standard><GUID>3</GUID></Standard>
+  </Values></Asset>
+</AssetList>"#;
+        let entry = |guid: &str, state, reason, cycle_time: &str| {
+            (guid.to_string(), state, reason, cycle_time.to_string())
+        };
+        assert_eq!(
+            build_assets(assets, r#"excluded_guids = ["1"]"#),
+            [
+                entry("1", State::Excluded, Reason::ExcludedGuid, "60"),
+                entry("2", State::Included, Reason::Inherited, "45"),
+                entry("3", State::Included, Reason::Inherited, "45"),
+            ]
+        );
+    }
+
+    #[test]
+    fn listed_guids_win_over_selectors_and_forcing_over_excluding() {
+        let assets = r#"<AssetList>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>1</GUID></Standard><FactoryBase><CycleTime>10</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>2</GUID></Standard><FactoryBase><CycleTime>10</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>3</GUID></Standard><FactoryBase><CycleTime>10</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>4</GUID></Standard><FactoryBase><CycleTime>30</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>4</BaseAssetGUID><Values>
+    <Standard><GUID>5</GUID></Standard><FactoryBase><CycleTime>50</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>3</BaseAssetGUID><Values>
+    <Standard><GUID>6</GUID></Standard><FactoryBase><CycleTime>50</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>7</GUID></Standard>
+  </Values></Asset>
+</AssetList>"#;
+        let lists = r#"forced_guids = ["1"]
+excluded_guids = ["1", "2"]
+forced_where = "FactoryBase/CycleTime < 20"
+excluded_where = "FactoryBase/CycleTime < 40""#;
+        let states = build_assets(assets, lists)
+            .into_iter()
+            .map(|(guid, state, reason, _)| (guid, state, reason))
+            .collect::<Vec<_>>();
+        let entry = |guid: &str, state, reason| (guid.to_string(), state, reason);
+        assert_eq!(
+            states,
+            [
+                entry("1", State::Forced, Reason::ForcedGuid),
+                entry("2", State::Excluded, Reason::ExcludedGuid),
+                entry("3", State::Forced, Reason::ForcedSelector),
+                entry("4", State::Excluded, Reason::ExcludedSelector),
+                entry("5", State::ExcludedByAncestor, Reason::Inherited),
+                entry("6", State::ForcedByAncestor, Reason::Inherited),
+                entry("7", State::Included, Reason::Inherited),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_assets_inheriting_from_each_other() {
+        let assets = r#"<AssetList>
+  <Asset><BaseAssetGUID>2</BaseAssetGUID><Values>
+    <Standard><GUID>1</GUID></Standard>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>1</BaseAssetGUID><Values>
+    <Standard><GUID>2</GUID></Standard>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>2</BaseAssetGUID><Values>
+    <Standard><GUID>3</GUID></Standard>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>4</GUID></Standard>
+  </Values></Asset>
+</AssetList>"#;
+        assert_eq!(
+            build_assets(assets, r#"excluded_where = "FactoryBase/CycleTime > 0""#),
+            [(
+                "4".to_string(),
+                State::Excluded,
+                Reason::ExcludedSelector,
+                "60".to_string()
+            )]
+        );
+    }
+}
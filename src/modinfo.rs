@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+const DEFAULT_VERSION: &str = "1.0.0";
+const DEFAULT_LANGUAGE: &str = "English";
+
+/// A text shown by mod managers, keyed by language.
+pub(crate) type Localized = BTreeMap<String, String>;
+
+/// The optional `[mod.modinfo]` table of a recipe.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ModInfoSettings {
+    version: Option<String>,
+    description: Option<LocalizedDefinition>,
+    category: Option<LocalizedDefinition>,
+    difficulty: Option<LocalizedDefinition>,
+    #[serde(default)]
+    load_after_ids: Vec<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+/// Either a plain text, which is used for English, or a table of language to text.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum LocalizedDefinition {
+    Text(String),
+    Translations(Localized),
+}
+
+impl From<&LocalizedDefinition> for Localized {
+    fn from(definition: &LocalizedDefinition) -> Localized {
+        match definition {
+            LocalizedDefinition::Text(text) => {
+                Localized::from([(DEFAULT_LANGUAGE.to_string(), text.clone())])
+            }
+            LocalizedDefinition::Translations(translations) => translations.clone(),
+        }
+    }
+}
+
+/// The `modinfo.json` read by the mod loader and mod managers.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ModInfo {
+    version: String,
+    #[serde(rename = "ModID")]
+    mod_id: String,
+    mod_dependencies: Vec<String>,
+    load_after_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<Localized>,
+    mod_name: Localized,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<Localized>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    difficulty: Option<Localized>,
+}
+
+impl ModInfo {
+    /// The mod loads after the mods of the recipe's settings and the input mods it patches.
+    pub(crate) fn new(
+        display_name: &str,
+        settings: &ModInfoSettings,
+        patched_mod_ids: &BTreeSet<String>,
+    ) -> ModInfo {
+        let mut load_after_ids = settings.load_after_ids.clone();
+        for mod_id in patched_mod_ids {
+            if !load_after_ids.contains(mod_id) {
+                load_after_ids.push(mod_id.clone());
+            }
+        }
+
+        ModInfo {
+            version: settings
+                .version
+                .clone()
+                .unwrap_or_else(|| DEFAULT_VERSION.to_string()),
+            mod_id: mod_id(display_name),
+            mod_dependencies: settings.dependencies.clone(),
+            load_after_ids,
+            category: settings.category.as_ref().map(Localized::from),
+            mod_name: Localized::from([(DEFAULT_LANGUAGE.to_string(), display_name.to_string())]),
+            description: settings.description.as_ref().map(Localized::from),
+            difficulty: settings.difficulty.as_ref().map(Localized::from),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// The parts of the `modinfo.json` of an input mod that matter for the load order.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct InputModInfo {
+    #[serde(rename = "ModID")]
+    pub(crate) mod_id: Option<String>,
+    #[serde(default)]
+    pub(crate) load_after_ids: Vec<String>,
+}
+
+/// Derives a stable ModID from the display name, `JJ's Enhanced Ship Building Times` becomes
+/// `jjs-enhanced-ship-building-times`.
+pub(crate) fn mod_id(display_name: &str) -> String {
+    display_name
+        .to_lowercase()
+        .replace('\'', "")
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
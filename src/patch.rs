@@ -0,0 +1,628 @@
+use crate::{
+    element::Element,
+    error::Error,
+    xpath::{self, XPath},
+};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Applies the ModOps of an input mod's file onto the documents of the file it patches, like the
+/// game's mod loader does.
+///
+/// The changed elements are marked with the ModID of the mod, if the file belongs to one.
+///
+/// ModOps that match nothing are skipped silently, the mod loader reports those itself. ModOps
+/// that can not be applied are returned as problems, the remaining ones are still applied. The
+/// paths of the files included by the ModOps are returned alongside.
+///
+/// An `Include` is relative to the file containing it, or to `mod_root` if it starts with `/`.
+/// Files outside of `mod_root` and files including themselves are problems.
+pub(crate) fn apply_mod_ops(
+    documents: &mut [Element],
+    mod_ops_path: &Path,
+    mod_root: &Path,
+    xml_string: &str,
+    mod_id: Option<&str>,
+) -> (Vec<PathBuf>, Vec<Error>) {
+    let mut patcher = Patcher {
+        documents: documents
+            .iter_mut()
+            .map(|root| Document {
+                root,
+                guid_index: None,
+            })
+            .collect(),
+        mod_id,
+        mod_root,
+        including: Vec::new(),
+        included: Vec::new(),
+        errors: Vec::new(),
+    };
+    patcher.apply_file(mod_ops_path, xml_string);
+    (patcher.included, patcher.errors)
+}
+
+struct Patcher<'a> {
+    documents: Vec<Document<'a>>,
+    mod_id: Option<&'a str>,
+    mod_root: &'a Path,
+    /// The files being applied, the first one including the second and so on.
+    including: Vec<PathBuf>,
+    included: Vec<PathBuf>,
+    errors: Vec<Error>,
+}
+
+struct Document<'a> {
+    root: &'a mut Element,
+    /// The paths of the assets by GUID, built when needed and dropped when assets move.
+    guid_index: Option<HashMap<String, Vec<Vec<usize>>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ModOpType {
+    Add,
+    Replace,
+    Remove,
+    Merge,
+    AddNextSibling,
+    AddPrevSibling,
+}
+
+impl Patcher<'_> {
+    fn apply_file(&mut self, path: &Path, xml_string: &str) {
+        let xml = match roxmltree::Document::parse(xml_string) {
+            Ok(xml) => xml,
+            Err(source) => {
+                self.errors.push(Error::Xml {
+                    path: path.to_path_buf(),
+                    source,
+                });
+                return;
+            }
+        };
+        let root = xml.root_element();
+        self.including.push(path.to_path_buf());
+        self.apply_children(path, &root, &format!("/{}[1]", root.tag_name().name()));
+        self.including.pop();
+    }
+
+    fn apply_children(&mut self, path: &Path, node: &roxmltree::Node<'_, '_>, xpath: &str) {
+        let mut counters: HashMap<&str, usize> = HashMap::new();
+        node.children()
+            .filter(|child| child.is_element())
+            .for_each(|child| {
+                let name = child.tag_name().name();
+                let count = counters.entry(name).or_default();
+                *count += 1;
+                let child_xpath = format!("{xpath}/{name}[{count}]");
+                let result = match name {
+                    "ModOp" => self.apply_mod_op(&child),
+                    "Group" => {
+                        self.apply_children(path, &child, &child_xpath);
+                        Ok(())
+                    }
+                    "Include" => self.include(path, &child),
+                    _ => Err(format!("Unknown element {name}")),
+                };
+                if let Err(message) = result {
+                    self.errors.push(Error::ModOp {
+                        path: path.to_path_buf(),
+                        xpath: child_xpath,
+                        message,
+                    });
+                }
+            });
+    }
+
+    fn include(&mut self, path: &Path, node: &roxmltree::Node<'_, '_>) -> Result<(), String> {
+        let file = node
+            .attribute("File")
+            .ok_or_else(|| "Include without File".to_string())?;
+        let include_path = match file.strip_prefix('/') {
+            Some(file) => self.mod_root.join(file),
+            None => path.parent().unwrap_or(Path::new("")).join(file),
+        };
+        let include_path = resolve_within(self.mod_root, &include_path)
+            .ok_or_else(|| format!("Include {file} is outside of the mod"))?;
+        if self.including.iter().any(|including| {
+            resolve_within(self.mod_root, including).as_ref() == Some(&include_path)
+        }) {
+            return Err(format!("Include {file} includes itself"));
+        }
+        match std::fs::read_to_string(&include_path) {
+            Ok(xml_string) => {
+                self.apply_file(&include_path, &xml_string);
+                self.included.push(include_path);
+            }
+            Err(source) => self.errors.push(Error::Io {
+                path: include_path,
+                source,
+            }),
+        }
+        Ok(())
+    }
+
+    fn apply_mod_op(&mut self, node: &roxmltree::Node<'_, '_>) -> Result<(), String> {
+        if node.attribute("Skip") == Some("1") {
+            return Ok(());
+        }
+
+        let mod_op_type = match node.attribute("Type").map(str::to_lowercase).as_deref() {
+            Some("add") => ModOpType::Add,
+            Some("replace") => ModOpType::Replace,
+            Some("remove") => ModOpType::Remove,
+            Some("merge") => ModOpType::Merge,
+            Some("addnextsibling") => ModOpType::AddNextSibling,
+            Some("addprevsibling") => ModOpType::AddPrevSibling,
+            Some(_) => {
+                return Err(format!(
+                    "Unsupported Type {}",
+                    node.attribute("Type").unwrap_or_default()
+                ))
+            }
+            None => return Err("ModOp without Type".to_string()),
+        };
+
+        let condition = node
+            .attribute("Condition")
+            .map(|condition| match condition.trim().strip_prefix('!') {
+                Some(negated) => XPath::parse(negated).map(|xpath| (xpath, false)),
+                None => XPath::parse(condition).map(|xpath| (xpath, true)),
+            })
+            .transpose()?;
+        let guids = node
+            .attribute("GUID")
+            .map(|guids| {
+                guids
+                    .split(',')
+                    .map(|guid| guid.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let path = match (node.attribute("Path"), guids.is_empty()) {
+            (Some(path), _) => XPath::parse(path)?,
+            (None, false) => XPath::parse("/")?,
+            (None, true) => return Err("ModOp without Path or GUID".to_string()),
+        };
+        let content = node
+            .children()
+            .filter(|child| child.is_element())
+            .map(|child| Element::from_node(&child))
+            .collect::<Vec<_>>();
+
+        let mod_id = self.mod_id;
+        self.documents.iter_mut().try_for_each(|document| {
+            if let Some((condition, expected)) = &condition {
+                if document.select(condition).is_empty() == *expected {
+                    return Ok(());
+                }
+            }
+
+            let mut targets = match guids.is_empty() {
+                true => document.select(&path),
+                false => guids
+                    .iter()
+                    .filter_map(|guid| document.assets(guid).first().cloned())
+                    .collect::<Vec<_>>()
+                    .iter()
+                    .flat_map(|asset| path.select_below(document.root, asset))
+                    .collect(),
+            };
+            // Later nodes first, so changing one does not move the ones still to change.
+            targets.sort();
+            targets.dedup();
+            targets.reverse();
+            targets
+                .iter()
+                .try_for_each(|target| document.apply(mod_op_type, target, content.clone(), mod_id))
+        })
+    }
+}
+
+impl Document<'_> {
+    /// The assets with the GUID in document order.
+    fn assets(&mut self, guid: &str) -> &[Vec<usize>] {
+        let root = &*self.root;
+        self.guid_index
+            .get_or_insert_with(|| {
+                let mut index = HashMap::new();
+                collect_assets(root, &mut Vec::new(), &mut index);
+                index
+            })
+            .get(guid)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// The elements the absolute path selects, with the assets of a path starting with their
+    /// GUID looked up instead of searched for.
+    fn select(&mut self, xpath: &XPath) -> Vec<Vec<usize>> {
+        let Some((guid, rest)) = xpath.asset_guid() else {
+            return xpath.select(self.root, &[]);
+        };
+        let assets = self.assets(guid).to_vec();
+        let mut paths = assets
+            .iter()
+            .flat_map(|asset| rest.select(self.root, asset))
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    fn apply(
+        &mut self,
+        mod_op_type: ModOpType,
+        target: &[usize],
+        mut content: Vec<Element>,
+        mod_id: Option<&str>,
+    ) -> Result<(), String> {
+        let mark = |changed_by: &mut Vec<String>| changed_by.extend(mod_id.map(str::to_string));
+        let parent_path = target.split_last().map(|(_, parent_path)| parent_path);
+        let changed_path = match mod_op_type {
+            ModOpType::Add | ModOpType::Merge => target,
+            _ => parent_path
+                .ok_or_else(|| format!("{mod_op_type:?} can not be applied to the root element"))?,
+        };
+        if !self.keeps_guid_index(changed_path) {
+            self.guid_index = None;
+        }
+
+        match mod_op_type {
+            ModOpType::Add | ModOpType::AddNextSibling | ModOpType::AddPrevSibling => content
+                .iter_mut()
+                .for_each(|element| element.added_by = mod_id.map(str::to_string)),
+            ModOpType::Replace => content
+                .iter_mut()
+                .for_each(|element| mark(&mut element.changed_by)),
+            ModOpType::Remove | ModOpType::Merge => (),
+        }
+
+        match mod_op_type {
+            ModOpType::Add => xpath::get_mut(self.root, target).children.extend(content),
+            ModOpType::Merge => {
+                let element = xpath::get_mut(self.root, target);
+                mark(&mut element.changed_by);
+                merge(element, content);
+            }
+            ModOpType::Replace
+            | ModOpType::Remove
+            | ModOpType::AddNextSibling
+            | ModOpType::AddPrevSibling => {
+                let (&index, _) = target.split_last().unwrap();
+                let parent = xpath::get_mut(self.root, changed_path);
+                let range = match mod_op_type {
+                    ModOpType::Replace | ModOpType::Remove => index..index + 1,
+                    ModOpType::AddNextSibling => index + 1..index + 1,
+                    _ => index..index,
+                };
+                if mod_op_type == ModOpType::Remove {
+                    content.clear();
+                    mark(&mut parent.changed_by);
+                }
+                parent.children.splice(range, content);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the assets keep their place and their GUID when the children of the element at
+    /// the path change. Assets only move if elements above them change, and only the `Values`
+    /// and `Standard` of an asset lead to its GUID.
+    fn keeps_guid_index(&self, path: &[usize]) -> bool {
+        let mut element = &*self.root;
+        let mut names = vec![element.name.as_str()];
+        path.iter().for_each(|&index| {
+            element = &element.children[index];
+            names.push(element.name.as_str());
+        });
+        match names.iter().rposition(|&name| name == "Asset") {
+            Some(asset) => !matches!(names[asset + 1..], [] | ["Values"] | ["Values", "Standard"]),
+            None => false,
+        }
+    }
+}
+
+/// The path with `.` and `..` resolved without looking at the file system, `None` if it is not
+/// inside `root`.
+fn resolve_within(root: &Path, path: &Path) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for component in path.strip_prefix(root).ok()?.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => (),
+            Component::ParentDir if resolved != root => {
+                resolved.pop();
+            }
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// Merges the content into the element: children with the same name are merged, leaves
+/// replaced and the others added.
+fn merge(element: &mut Element, content: Vec<Element>) {
+    for child in content {
+        match element
+            .children
+            .iter_mut()
+            .find(|existing| existing.name == child.name)
+        {
+            Some(existing) if !existing.children.is_empty() && !child.children.is_empty() => {
+                merge(existing, child.children)
+            }
+            Some(existing) => *existing = child,
+            None => element.children.push(child),
+        }
+    }
+}
+
+fn collect_assets(
+    element: &Element,
+    path: &mut Vec<usize>,
+    index: &mut HashMap<String, Vec<Vec<usize>>>,
+) {
+    if element.name == "Asset" {
+        let guid = element
+            .child("Values")
+            .ok()
+            .flatten()
+            .and_then(|values| values.child("Standard").ok().flatten())
+            .and_then(|standard| standard.child("GUID").ok().flatten())
+            .and_then(|guid| guid.text.as_deref());
+        if let Some(guid) = guid {
+            index
+                .entry(guid.trim().to_string())
+                .or_default()
+                .push(path.clone());
+        }
+    }
+
+    element.children.iter().enumerate().for_each(|(i, child)| {
+        path.push(i);
+        collect_assets(child, path, index);
+        path.pop();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Asset><Values><Standard><GUID>1</GUID></Standard><Cost><Amount>10</Amount></Cost></Values></Asset>
+  <Asset><Values><Standard><GUID>2</GUID></Standard><Cost><Amount>20</Amount></Cost></Values></Asset>
+</AssetList>"#;
+
+    /// Applies the ModOps to the assets in a mod at `mod_root`, returning the resulting assets
+    /// and the messages of the problems.
+    fn patch_file(mod_root: &Path, path: &Path, mod_ops: &str) -> (String, Vec<String>) {
+        let xml = roxmltree::Document::parse(ASSETS).unwrap();
+        let mut documents = [Element::from_node(&xml.root_element())];
+        let (_, errors) = apply_mod_ops(&mut documents, path, mod_root, mod_ops, Some("mod"));
+        let messages = errors
+            .into_iter()
+            .map(|error| match error {
+                Error::ModOp { message, .. } => message,
+                error => format!("{error:?}"),
+            })
+            .collect();
+        (documents[0].inner_xml(), messages)
+    }
+
+    fn patch(mod_ops: &str) -> (String, Vec<String>) {
+        let mod_root = Path::new("mod");
+        patch_file(mod_root, &mod_root.join("assets.xml"), mod_ops)
+    }
+
+    fn asset(guid: &str, values: &str) -> String {
+        format!("<Asset><Values><Standard><GUID>{guid}</GUID></Standard>{values}</Values></Asset>")
+    }
+
+    fn assets(first: &str, second: &str) -> String {
+        asset("1", first) + &asset("2", second)
+    }
+
+    const COST_1: &str = "<Cost><Amount>10</Amount></Cost>";
+    const COST_2: &str = "<Cost><Amount>20</Amount></Cost>";
+
+    #[test]
+    fn applies_every_mod_op_type() {
+        let cases = [
+            (
+                r#"<ModOp Type="Add" Path="//Asset[Values/Standard/GUID='1']/Values/Cost"><Extra>1</Extra></ModOp>"#,
+                assets("<Cost><Amount>10</Amount><Extra>1</Extra></Cost>", COST_2),
+            ),
+            (
+                r#"<ModOp Type="Replace" Path="//Asset[Values/Standard/GUID='2']/Values/Cost/Amount"><Amount>5</Amount></ModOp>"#,
+                assets(COST_1, "<Cost><Amount>5</Amount></Cost>"),
+            ),
+            (
+                r#"<ModOp Type="remove" Path="//Cost/Amount" />"#,
+                assets("<Cost></Cost>", "<Cost></Cost>"),
+            ),
+            (
+                r#"<ModOp Type="Merge" Path="//Asset[Values/Standard/GUID='1']/Values"><Cost><Amount>3</Amount></Cost><Extra>1</Extra></ModOp>"#,
+                assets("<Cost><Amount>3</Amount></Cost><Extra>1</Extra>", COST_2),
+            ),
+            (
+                r#"<ModOp Type="AddNextSibling" Path="//Asset[Values/Standard/GUID='1']/Values/Standard"><Extra>1</Extra></ModOp>"#,
+                assets(&format!("<Extra>1</Extra>{COST_1}"), COST_2),
+            ),
+            (
+                r#"<ModOp Type="AddPrevSibling" GUID="2" Path="/Values/Cost"><Extra>1</Extra></ModOp>"#,
+                assets(COST_1, &format!("<Extra>1</Extra>{COST_2}")),
+            ),
+        ];
+        for (mod_op, expected) in cases {
+            let (result, errors) = patch(&format!("<ModOps>{mod_op}</ModOps>"));
+            assert_eq!(result, expected, "{mod_op}");
+            assert!(errors.is_empty(), "{mod_op}: {errors:?}");
+        }
+    }
+
+    #[test]
+    fn marks_the_changed_elements_with_the_mod() {
+        let xml = roxmltree::Document::parse(ASSETS).unwrap();
+        let mut documents = [Element::from_node(&xml.root_element())];
+        let mod_ops = r#"<ModOps>
+  <ModOp Type="Add" GUID="1" Path="/Values"><Extra>1</Extra></ModOp>
+  <ModOp Type="Replace" GUID="2" Path="/Values/Cost"><Cost /></ModOp>
+</ModOps>"#;
+        let mod_root = Path::new("mod");
+        apply_mod_ops(&mut documents, mod_root, mod_root, mod_ops, Some("mod"));
+
+        let values = |guid: usize| &documents[0].children[guid].children[0];
+        assert_eq!(values(0).children[2].added_by.as_deref(), Some("mod"));
+        assert_eq!(values(1).children[1].changed_by, ["mod"]);
+    }
+
+    #[test]
+    fn guids_select_the_assets_and_keep_up_with_changes() {
+        let (result, errors) = patch(
+            r#"<ModOps>
+  <ModOp Type="AddNextSibling" GUID="1" Path="/"><Asset><Values><Standard><GUID>3</GUID></Standard></Values></Asset></ModOp>
+  <ModOp Type="Add" Path="//Asset[Values/Standard/GUID='2']/Values"><Extra>2</Extra></ModOp>
+  <ModOp Type="Add" GUID="3,1" Path="/Values"><Extra>3</Extra></ModOp>
+</ModOps>"#,
+        );
+        let expected = asset("1", &format!("{COST_1}<Extra>3</Extra>"))
+            + &asset("3", "<Extra>3</Extra>")
+            + &asset("2", &format!("{COST_2}<Extra>2</Extra>"));
+        assert_eq!(result, expected);
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn conditions_skip_the_mod_op() {
+        let mod_ops = |condition: &str| {
+            format!(
+                r#"<ModOps><ModOp Type="Remove" Condition="{condition}" Path="//Asset[Values/Standard/GUID='2']" /></ModOps>"#
+            )
+        };
+        let removed = asset("1", COST_1);
+        let kept = assets(COST_1, COST_2);
+        for (condition, expected) in [
+            ("//Asset[Values/Standard/GUID='1']", &removed),
+            ("//Asset[Values/Standard/GUID='3']", &kept),
+            ("!//Asset[Values/Standard/GUID='3']", &removed),
+            (" !//Cost", &kept),
+        ] {
+            let (result, errors) = patch(&mod_ops(condition));
+            assert_eq!(&result, expected, "{condition}");
+            assert!(errors.is_empty(), "{condition}: {errors:?}");
+        }
+    }
+
+    #[test]
+    fn skips_mod_ops_that_match_nothing_or_are_skipped() {
+        for mod_op in [
+            r#"<ModOp Type="Remove" Path="//Asset[Values/Standard/GUID='3']" />"#,
+            r#"<ModOp Type="Remove" Path="//Asset[Values/Standard/GUID='3']" AllowNoMatch="1" />"#,
+            r#"<ModOp Type="Remove" GUID="3" Path="/Values" />"#,
+            r#"<ModOp Type="Remove" Path="//Cost" Skip="1" />"#,
+        ] {
+            let (result, errors) = patch(&format!("<ModOps>{mod_op}</ModOps>"));
+            assert_eq!(result, assets(COST_1, COST_2), "{mod_op}");
+            assert!(errors.is_empty(), "{mod_op}: {errors:?}");
+        }
+    }
+
+    #[test]
+    fn reports_mod_ops_that_can_not_be_applied_and_applies_the_rest() {
+        let (result, errors) = patch(
+            r#"<ModOps>
+  <ModOp Type="Rename" Path="//Cost" />
+  <ModOp Path="//Cost" />
+  <ModOp Type="Remove" />
+  <ModOp Type="Remove" Path="//Cost[" />
+  <ModOp Type="Remove" Condition="!//" Path="//Cost" />
+  <ModOp Type="Remove" Path="/AssetList" />
+  <Group>
+    <Replace />
+    <ModOp Type="Remove" GUID="1" Path="/Values/Cost" />
+  </Group>
+</ModOps>"#,
+        );
+        assert_eq!(result, assets("", COST_2));
+        assert_eq!(
+            errors,
+            [
+                "Unsupported Type Rename",
+                "ModOp without Type",
+                "ModOp without Path or GUID",
+                "Unbalanced brackets or quotes in 'Cost['",
+                "'//' ends with '/'",
+                "Remove can not be applied to the root element",
+                "Unknown element Replace",
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_file_or_the_mod() {
+        let mod_root = tempfile::tempdir().unwrap();
+        let mod_root = mod_root.path();
+        fs::create_dir_all(mod_root.join("sub")).unwrap();
+        fs::write(
+            mod_root.join("sub/one.include.xml"),
+            r#"<ModOps><ModOp Type="Remove" GUID="1" Path="/Values/Cost" /><Include File="/two.include.xml" /></ModOps>"#,
+        )
+        .unwrap();
+        fs::write(
+            mod_root.join("two.include.xml"),
+            r#"<ModOps><ModOp Type="Remove" GUID="2" Path="/Values/Cost" /></ModOps>"#,
+        )
+        .unwrap();
+
+        let (result, errors) = patch_file(
+            mod_root,
+            &mod_root.join("sub/assets.xml"),
+            r#"<ModOps><Include File="./one.include.xml" /></ModOps>"#,
+        );
+        assert_eq!(result, assets("", ""));
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn rejects_includes_outside_of_the_mod_and_include_cycles() {
+        let directory = tempfile::tempdir().unwrap();
+        let mod_root = directory.path().join("mod");
+        fs::create_dir_all(mod_root.join("sub")).unwrap();
+        fs::write(
+            directory.path().join("outside.include.xml"),
+            r#"<ModOps><ModOp Type="Remove" Path="//Cost" /></ModOps>"#,
+        )
+        .unwrap();
+        fs::write(
+            mod_root.join("sub/loop.include.xml"),
+            r#"<ModOps><Include File="../sub/loop.include.xml" /><Include File="/assets.xml" /></ModOps>"#,
+        )
+        .unwrap();
+
+        let (result, errors) = patch_file(
+            &mod_root,
+            &mod_root.join("assets.xml"),
+            r#"<ModOps>
+  <Include File="../outside.include.xml" />
+  <Include File="/../outside.include.xml" />
+  <Include File="assets.xml" />
+  <Include File="sub/loop.include.xml" />
+</ModOps>"#,
+        );
+        assert_eq!(result, assets(COST_1, COST_2));
+        assert_eq!(
+            errors,
+            [
+                "Include ../outside.include.xml is outside of the mod",
+                "Include /../outside.include.xml is outside of the mod",
+                "Include assets.xml includes itself",
+                "Include ../sub/loop.include.xml includes itself",
+                "Include /assets.xml includes itself",
+            ]
+        );
+    }
+}
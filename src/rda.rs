@@ -0,0 +1,615 @@
+use crate::error::Error;
+use flate2::read::ZlibDecoder;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+const MAGIC_V2_2: &[u8] = b"Resource File V2.2";
+/// The version 2.0 magic is written in UTF-16.
+const MAGIC_V2_0: &str = "Resource File V2.0";
+
+const COMPRESSED: u32 = 1;
+const ENCRYPTED: u32 = 2;
+const MEMORY_RESIDENT: u32 = 4;
+const DELETED: u32 = 8;
+
+/// The UTF-16 file name at the start of every directory entry.
+const FILE_NAME_SIZE: usize = 520;
+
+/// An `.rda` archive of the game in version 2.0 or 2.2.
+///
+/// Only the directories are read when opening it, the files are read on request.
+#[derive(Debug)]
+pub(crate) struct Archive {
+    pub(crate) path: PathBuf,
+    /// The files of all blocks in the order of the blocks.
+    pub(crate) files: Vec<ArchivedFile>,
+    /// The decompressed contents of the memory-resident blocks, which store their files together.
+    resident_blocks: Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ArchivedFile {
+    /// The path inside the archive, like `data/config/export/main/asset/assets.xml`.
+    pub(crate) name: String,
+    offset: u64,
+    compressed_size: u64,
+    size: u64,
+    storage: Storage,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    Stored,
+    Compressed,
+    /// The offset is relative to the memory-resident block with the index.
+    Resident(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Version {
+    V2_0,
+    V2_2,
+}
+
+impl Version {
+    /// The size of the integers in the headers and directory entries.
+    fn int_size(self) -> u64 {
+        match self {
+            Version::V2_0 => 4,
+            Version::V2_2 => 8,
+        }
+    }
+
+    /// The offset of the first block's offset, behind the magic and unused bytes.
+    fn first_block_offset_position(self) -> u64 {
+        match self {
+            Version::V2_0 => 36 + 1008,
+            Version::V2_2 => 18 + 766,
+        }
+    }
+}
+
+struct BlockHeader {
+    flags: u32,
+    file_count: u32,
+    directory_size: u64,
+    decompressed_size: u64,
+    next_block: u64,
+}
+
+impl Archive {
+    /// Reads the directories of all blocks up to the end of the file, deleted blocks are left out.
+    pub(crate) fn open(path: &Path) -> Result<Archive, Error> {
+        let mut reader = Reader::open(path)?;
+        let version = reader.version()?;
+
+        let mut archive = Archive {
+            path: path.to_path_buf(),
+            files: Vec::new(),
+            resident_blocks: Vec::new(),
+        };
+        let mut offset = reader.int_at(version, version.first_block_offset_position())?;
+        while offset != reader.length {
+            let block = reader.block_header(version, offset)?;
+            if block.next_block <= offset {
+                return Err(archive.error(format!("Block at {offset} points back to its start")));
+            }
+
+            match (block.flags & DELETED != 0, block.flags & ENCRYPTED != 0) {
+                (true, _) => (),
+                (false, true) => {
+                    return Err(archive.error(format!(
+                        "Block at {offset} is encrypted, which is not supported"
+                    )))
+                }
+                (false, false) => archive.read_block(&mut reader, version, offset, &block)?,
+            }
+            offset = block.next_block;
+        }
+
+        Ok(archive)
+    }
+
+    fn read_block(
+        &mut self,
+        reader: &mut Reader,
+        version: Version,
+        offset: u64,
+        block: &BlockHeader,
+    ) -> Result<(), Error> {
+        let compressed = block.flags & COMPRESSED != 0;
+        let int_size = version.int_size();
+
+        // A block is stored in front of its header: the files, the directory, and for a
+        // memory-resident block the sizes of its files taken together.
+        let (directory_end, storage) = match block.flags & MEMORY_RESIDENT != 0 {
+            true => {
+                let sizes_offset = offset
+                    .checked_sub(2 * int_size)
+                    .ok_or_else(|| self.error(format!("Block at {offset} is truncated")))?;
+                let compressed_size = reader.int_at(version, sizes_offset)?;
+                let size = reader.int_at(version, sizes_offset + int_size)?;
+                let data_offset = sizes_offset
+                    .checked_sub(block.directory_size + compressed_size)
+                    .ok_or_else(|| self.error(format!("Block at {offset} is truncated")))?;
+                let data = reader.read_at(data_offset, compressed_size)?;
+                let data = self.decompress(data, compressed, size)?;
+                self.resident_blocks.push(data);
+                (
+                    sizes_offset,
+                    Storage::Resident(self.resident_blocks.len() - 1),
+                )
+            }
+            false => (
+                offset,
+                match compressed {
+                    true => Storage::Compressed,
+                    false => Storage::Stored,
+                },
+            ),
+        };
+
+        let directory_offset = directory_end
+            .checked_sub(block.directory_size)
+            .ok_or_else(|| self.error(format!("Block at {offset} is truncated")))?;
+        let directory = reader.read_at(directory_offset, block.directory_size)?;
+        let directory = self.decompress(directory, compressed, block.decompressed_size)?;
+
+        let entry_size = FILE_NAME_SIZE + 5 * int_size as usize;
+        if (block.file_count as usize)
+            .checked_mul(entry_size)
+            .is_none_or(|size| directory.len() < size)
+        {
+            return Err(self.error(format!(
+                "Directory of the block at {offset} is too short for {} files",
+                block.file_count
+            )));
+        }
+        directory
+            .chunks_exact(entry_size)
+            .take(block.file_count as usize)
+            .for_each(|entry| {
+                let (name, numbers) = entry.split_at(FILE_NAME_SIZE);
+                let number =
+                    |index: usize| read_int(version, &numbers[index * int_size as usize..]);
+                self.files.push(ArchivedFile {
+                    name: read_file_name(name),
+                    offset: number(0),
+                    compressed_size: number(1),
+                    size: number(2),
+                    storage,
+                });
+            });
+        Ok(())
+    }
+
+    /// Reads and decompresses the file with the index.
+    pub(crate) fn read(&self, index: usize) -> Result<Vec<u8>, Error> {
+        let file = &self.files[index];
+        match file.storage {
+            Storage::Resident(block) => {
+                let data = &self.resident_blocks[block];
+                let start = file.offset as usize;
+                match start
+                    .checked_add(file.size as usize)
+                    .and_then(|end| data.get(start..end))
+                {
+                    Some(bytes) => Ok(bytes.to_vec()),
+                    None => Err(self.error(format!("{} is outside of its block", file.name))),
+                }
+            }
+            Storage::Stored | Storage::Compressed => {
+                let mut reader = Reader::open(&self.path)?;
+                let data = reader.read_at(file.offset, file.compressed_size)?;
+                self.decompress(data, matches!(file.storage, Storage::Compressed), file.size)
+            }
+        }
+    }
+
+    fn decompress(&self, data: Vec<u8>, compressed: bool, size: u64) -> Result<Vec<u8>, Error> {
+        if !compressed {
+            return Ok(data);
+        }
+        let mut decompressed = Vec::new();
+        ZlibDecoder::new(data.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|source| Error::Io {
+                path: self.path.clone(),
+                source,
+            })?;
+        match decompressed.len() as u64 == size {
+            true => Ok(decompressed),
+            false => Err(self.error(format!(
+                "Decompressed {} bytes instead of {size}",
+                decompressed.len()
+            ))),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Archive {
+            path: self.path.clone(),
+            message,
+        }
+    }
+}
+
+struct Reader {
+    path: PathBuf,
+    file: File,
+    length: u64,
+}
+
+impl Reader {
+    fn open(path: &Path) -> Result<Reader, Error> {
+        let io_error = |source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        let file = File::open(path).map_err(io_error)?;
+        let length = file.metadata().map_err(io_error)?.len();
+        Ok(Reader {
+            path: path.to_path_buf(),
+            file,
+            length,
+        })
+    }
+
+    fn version(&mut self) -> Result<Version, Error> {
+        let magic_v2_0 = MAGIC_V2_0
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let start = self.read_at(0, magic_v2_0.len().min(self.length as usize) as u64)?;
+        match (start.starts_with(MAGIC_V2_2), start == magic_v2_0) {
+            (true, _) => Ok(Version::V2_2),
+            (false, true) => Ok(Version::V2_0),
+            (false, false) => Err(Error::Archive {
+                path: self.path.clone(),
+                message: "Not an RDA archive of version 2.0 or 2.2".to_string(),
+            }),
+        }
+    }
+
+    fn block_header(&mut self, version: Version, offset: u64) -> Result<BlockHeader, Error> {
+        let int_size = version.int_size();
+        let bytes = self.read_at(offset, 8 + 3 * int_size)?;
+        let int = |index: u64| read_int(version, &bytes[(8 + index * int_size) as usize..]);
+        Ok(BlockHeader {
+            flags: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            file_count: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            directory_size: int(0),
+            decompressed_size: int(1),
+            next_block: int(2),
+        })
+    }
+
+    fn int_at(&mut self, version: Version, offset: u64) -> Result<u64, Error> {
+        let bytes = self.read_at(offset, version.int_size())?;
+        Ok(read_int(version, &bytes))
+    }
+
+    /// Reads the bytes at the offset, an error if they are not all inside the file.
+    fn read_at(&mut self, offset: u64, size: u64) -> Result<Vec<u8>, Error> {
+        if offset.checked_add(size).is_none_or(|end| end > self.length) {
+            return Err(Error::Archive {
+                path: self.path.clone(),
+                message: format!("{size} bytes at {offset} are outside of the file"),
+            });
+        }
+        let mut bytes = vec![0; size as usize];
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(&mut bytes))
+            .map_err(|source| Error::Io {
+                path: self.path.clone(),
+                source,
+            })?;
+        Ok(bytes)
+    }
+}
+
+fn read_int(version: Version, bytes: &[u8]) -> u64 {
+    match version {
+        Version::V2_0 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as u64,
+        Version::V2_2 => u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+    }
+}
+
+/// The UTF-16 name of a directory entry, padded with zeros.
+fn read_file_name(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    /// A block to write, its flags and its files by name and content.
+    type Block<'a> = (u32, &'a [(&'a str, &'a str)]);
+
+    fn compress(compressed: bool, data: &[u8]) -> Vec<u8> {
+        if !compressed {
+            return data.to_vec();
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Writes an archive the way the game does, every block behind its files and directory.
+    fn archive(version: Version, blocks: &[Block]) -> Vec<u8> {
+        let int = |value: usize| match version {
+            Version::V2_0 => (value as u32).to_le_bytes().to_vec(),
+            Version::V2_2 => (value as u64).to_le_bytes().to_vec(),
+        };
+        let mut bytes = match version {
+            Version::V2_0 => MAGIC_V2_0
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+            Version::V2_2 => MAGIC_V2_2.to_vec(),
+        };
+        bytes.resize(version.first_block_offset_position() as usize, 0);
+        // Where the offset of the next block goes once it is known.
+        let mut next_block_position = bytes.len();
+        bytes.extend(int(0));
+
+        for (flags, files) in blocks {
+            let compressed = flags & COMPRESSED != 0;
+            let resident = flags & MEMORY_RESIDENT != 0;
+            let mut directory = Vec::new();
+            let mut resident_data = Vec::new();
+            for (name, content) in *files {
+                let content = content.as_bytes();
+                let (offset, stored_size) = match resident {
+                    true => {
+                        resident_data.extend(content);
+                        (resident_data.len() - content.len(), content.len())
+                    }
+                    false => {
+                        let stored = compress(compressed, content);
+                        bytes.extend(&stored);
+                        (bytes.len() - stored.len(), stored.len())
+                    }
+                };
+                let mut entry = name
+                    .encode_utf16()
+                    .flat_map(u16::to_le_bytes)
+                    .collect::<Vec<_>>();
+                entry.resize(FILE_NAME_SIZE, 0);
+                [offset, stored_size, content.len(), 0, 0]
+                    .into_iter()
+                    .for_each(|value| entry.extend(int(value)));
+                directory.extend(entry);
+            }
+
+            let resident_data_size = resident_data.len();
+            let resident_data = compress(compressed, &resident_data);
+            if resident {
+                bytes.extend(&resident_data);
+            }
+            let stored_directory = compress(compressed, &directory);
+            bytes.extend(&stored_directory);
+            if resident {
+                bytes.extend(int(resident_data.len()));
+                bytes.extend(int(resident_data_size));
+            }
+
+            let offset = bytes.len();
+            let next_block = int(offset);
+            bytes[next_block_position..next_block_position + next_block.len()]
+                .copy_from_slice(&next_block);
+            bytes.extend(flags.to_le_bytes());
+            bytes.extend((files.len() as u32).to_le_bytes());
+            bytes.extend(int(stored_directory.len()));
+            bytes.extend(int(directory.len()));
+            next_block_position = bytes.len();
+            bytes.extend(int(0));
+        }
+
+        let end = int(bytes.len());
+        bytes[next_block_position..next_block_position + end.len()].copy_from_slice(&end);
+        bytes
+    }
+
+    /// Opens the bytes as an archive, the file has to be kept while reading from it.
+    fn open(bytes: &[u8]) -> (NamedTempFile, Result<Archive, Error>) {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(bytes).unwrap();
+        let archive = Archive::open(file.path());
+        (file, archive)
+    }
+
+    fn message(result: Result<Archive, Error>) -> String {
+        match result {
+            Err(Error::Archive { message, .. }) => message,
+            result => panic!("Expected an archive error, got {result:?}"),
+        }
+    }
+
+    const BLOCKS: [Block; 5] = [
+        (
+            0,
+            &[
+                ("data/config/export/main/asset/assets.xml", "<AssetList />"),
+                ("data/config/gui/texts_english.xml", "<TextExport />"),
+            ],
+        ),
+        (
+            COMPRESSED,
+            &[(
+                "data/config/export/main/asset/properties.xml",
+                "<Properties />",
+            )],
+        ),
+        (DELETED, &[("data/deleted.xml", "<Deleted />")]),
+        (
+            COMPRESSED | MEMORY_RESIDENT,
+            &[
+                (
+                    "data/config/export/main/asset/templates.xml",
+                    "<Templates />",
+                ),
+                ("data/resident.xml", "<Resident>ü</Resident>"),
+            ],
+        ),
+        (
+            MEMORY_RESIDENT,
+            &[("data/stored_resident.xml", "<Stored />")],
+        ),
+    ];
+
+    #[test]
+    fn reads_the_files_of_every_kind_of_block() {
+        for version in [Version::V2_0, Version::V2_2] {
+            let (_file, archive) = open(&archive(version, &BLOCKS));
+            let archive = archive.unwrap();
+            let files = (0..archive.files.len())
+                .map(|index| {
+                    let content = String::from_utf8(archive.read(index).unwrap()).unwrap();
+                    (archive.files[index].name.as_str(), content)
+                })
+                .collect::<Vec<_>>();
+            let expected = BLOCKS
+                .iter()
+                .filter(|(flags, _)| flags & DELETED == 0)
+                .flat_map(|(_, files)| *files)
+                .map(|(name, content)| (*name, content.to_string()))
+                .collect::<Vec<_>>();
+            assert_eq!(files, expected, "{version:?}");
+        }
+    }
+
+    #[test]
+    fn reads_an_archive_without_blocks() {
+        for version in [Version::V2_0, Version::V2_2] {
+            let (_file, archive) = open(&archive(version, &[]));
+            assert!(archive.unwrap().files.is_empty(), "{version:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(
+            message(open(b"").1),
+            "Not an RDA archive of version 2.0 or 2.2"
+        );
+        assert_eq!(
+            message(open(b"<AssetList />").1),
+            "Not an RDA archive of version 2.0 or 2.2"
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_archives_without_panicking() {
+        for version in [Version::V2_0, Version::V2_2] {
+            let bytes = archive(version, &BLOCKS);
+            let (_file, archive) = open(&bytes);
+            let file_count = archive.unwrap().files.len();
+            for length in 0..bytes.len() {
+                // Cut right behind a block the archive just ends there, with the earlier blocks.
+                match open(&bytes[..length]) {
+                    (_, Err(_)) => (),
+                    (_file, Ok(archive)) => {
+                        assert!(archive.files.len() < file_count, "{version:?} to {length}");
+                        (0..archive.files.len()).for_each(|index| {
+                            archive.read(index).unwrap();
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_archives() {
+        let files: &[(&str, &str)] = &[("data/a.xml", "<A />")];
+        let bytes = archive(Version::V2_2, &[(COMPRESSED, files)]);
+        let header = bytes.len() - 32;
+        let with = |position: usize, value: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[position..position + value.len()].copy_from_slice(value);
+            open(&bytes).1
+        };
+
+        assert_eq!(
+            message(with(header + 24, &0u64.to_le_bytes())),
+            format!("Block at {header} points back to its start")
+        );
+        assert_eq!(
+            message(with(header, &ENCRYPTED.to_le_bytes())),
+            format!("Block at {header} is encrypted, which is not supported")
+        );
+        assert_eq!(
+            message(with(header + 4, &2u32.to_le_bytes())),
+            format!("Directory of the block at {header} is too short for 2 files")
+        );
+        assert_eq!(
+            message(with(header + 8, &(header as u64 + 1).to_le_bytes())),
+            format!("Block at {header} is truncated")
+        );
+        assert_eq!(
+            message(with(header + 16, &1u64.to_le_bytes())),
+            "Decompressed 560 bytes instead of 1"
+        );
+        assert!(matches!(
+            with(header - 10, b"garbage"),
+            Err(Error::Io { .. } | Error::Archive { .. })
+        ));
+        assert_eq!(
+            message(with(784, &(bytes.len() as u64 + 1).to_le_bytes())),
+            format!("32 bytes at {} are outside of the file", bytes.len() + 1)
+        );
+    }
+
+    #[test]
+    fn rejects_files_outside_of_the_archive() {
+        let files: &[(&str, &str)] = &[("data/a.xml", "<A />")];
+        for (flags, message) in [
+            (0, "1005 bytes at 792 are outside of the file"),
+            (MEMORY_RESIDENT, "data/a.xml is outside of its block"),
+        ] {
+            let mut bytes = archive(Version::V2_2, &[(flags, files)]);
+            // The sizes in the directory entry, behind the 5 bytes of the file.
+            let sizes = 792 + 5 + FILE_NAME_SIZE + 8;
+            bytes[sizes..sizes + 8].copy_from_slice(&1005u64.to_le_bytes());
+            bytes[sizes + 8..sizes + 16].copy_from_slice(&1005u64.to_le_bytes());
+            let (_file, archive) = open(&bytes);
+            let error = archive.unwrap().read(0).unwrap_err();
+            assert!(
+                matches!(&error, Error::Archive { message: m, .. } if m == message),
+                "{error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_resident_files_at_corrupt_offsets() {
+        let files: &[(&str, &str)] = &[("data/a.xml", "<A />")];
+        for offset in [6, u64::MAX - 1] {
+            let mut bytes = archive(Version::V2_2, &[(MEMORY_RESIDENT, files)]);
+            // The offset in the directory entry, behind the 5 bytes of the file.
+            let position = 792 + 5 + FILE_NAME_SIZE;
+            bytes[position..position + 8].copy_from_slice(&offset.to_le_bytes());
+            let (_file, archive) = open(&bytes);
+            let error = archive.unwrap().read(0).unwrap_err();
+            assert!(
+                matches!(&error, Error::Archive { message, .. }
+                    if message == "data/a.xml is outside of its block"),
+                "{offset}: {error:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,1206 @@
+use crate::{
+    database::{AssetDatabase, Entry, NodeType},
+    helper,
+    modinfo::ModInfoSettings,
+    selector::Selector,
+    transform::Transform,
+    xml_structure::{self, Content, ItemKey, Placement, XmlTag},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+use toml::Spanned;
+
+const BUILT_IN_RECIPES: &str = include_str!("../recipes/default.toml");
+
+#[derive(Debug, Clone)]
+pub(crate) struct Recipe {
+    pub(crate) name: String,
+    pub(crate) query: XmlTag,
+    pub(crate) excluded_templates: Vec<String>,
+    /// GUIDs, or names of assets until [`Recipe::resolve`] replaces them with their GUIDs.
+    pub(crate) forced_guids: Vec<String>,
+    pub(crate) excluded_guids: Vec<String>,
+    /// Assets matching it are excluded, unless their GUID is listed.
+    pub(crate) excluded_where: Option<Selector>,
+    /// Assets matching it are forced, unless their GUID is listed. Wins over `excluded_where`.
+    pub(crate) forced_where: Option<Selector>,
+    /// The paths the query was combined from if the recipe gives targets, checked against the
+    /// DefaultValues by [`Recipe::resolve`].
+    target_paths: Vec<Vec<String>>,
+    pub(crate) modinfo: ModInfoSettings,
+    source_name: String,
+    /// The line and column of every entry of the lists and of every target, for the problems
+    /// found once the game data is loaded.
+    locations: HashMap<(List, usize), (usize, usize)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum List {
+    ExcludedTemplates,
+    ForcedGuids,
+    ExcludedGuids,
+    Targets,
+}
+
+/// How similar a name has to be to one that is not found to be suggested, from 0 to 1.
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+const SUGGESTION_COUNT: usize = 3;
+
+const INVALID_STEP: &str = "is not a valid tag name or list item like 'Item[Product=1010017]'";
+
+#[derive(Debug)]
+pub(crate) struct RecipeError {
+    source_name: String,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.source_name, self.line, self.column, self.message
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeFile {
+    #[serde(rename = "mod")]
+    mods: Vec<RecipeDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeDefinition {
+    name: Spanned<String>,
+    query: Option<QueryDefinition>,
+    targets: Option<Spanned<Vec<TargetDefinition>>>,
+    #[serde(default)]
+    excluded_templates: Vec<Spanned<String>>,
+    #[serde(default)]
+    forced_guids: Vec<Spanned<String>>,
+    #[serde(default)]
+    excluded_guids: Vec<Spanned<String>>,
+    excluded_where: Option<Spanned<String>>,
+    forced_where: Option<Spanned<String>>,
+    #[serde(default)]
+    modinfo: ModInfoSettings,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueryDefinition {
+    name: Spanned<String>,
+    children: Option<Spanned<Vec<QueryDefinition>>>,
+    transform: Option<Spanned<Vec<Transform>>>,
+    action: Option<Spanned<Action>>,
+    insert_after: Option<Spanned<String>>,
+    insert_before: Option<Spanned<String>>,
+}
+
+/// A value to change given by its path, the paths of a recipe are combined into its query.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetDefinition {
+    path: Spanned<String>,
+    transform: Option<Spanned<Vec<Transform>>>,
+    action: Option<Spanned<Action>>,
+    insert_after: Option<Spanned<String>>,
+    insert_before: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Remove,
+}
+
+/// Loads the recipes from the given file, or the built-in recipes if no file is given.
+pub(crate) fn load_recipes(path: Option<&Path>) -> Result<Vec<Recipe>, RecipeError> {
+    match path {
+        Some(path) => {
+            let source_name = path.display().to_string();
+            let text = std::fs::read_to_string(path).map_err(|error| RecipeError {
+                source_name: source_name.clone(),
+                line: 1,
+                column: 1,
+                message: format!("Could not read recipe file: {error}"),
+            })?;
+            parse_recipes(&source_name, &text)
+        }
+        None => parse_recipes("<built-in recipes>", BUILT_IN_RECIPES),
+    }
+}
+
+/// The recipes of the text, the problems name the text by `source_name`.
+pub(crate) fn parse_recipes(source_name: &str, text: &str) -> Result<Vec<Recipe>, RecipeError> {
+    let validator = Validator { source_name, text };
+
+    let recipe_file: RecipeFile = toml::from_str(text).map_err(|error| {
+        let offset = error.span().map(|span| span.start).unwrap_or(0);
+        validator.error(offset, error.message().to_string())
+    })?;
+
+    let mut mod_names = HashSet::new();
+    recipe_file
+        .mods
+        .into_iter()
+        .map(|definition| {
+            if definition.name.get_ref().trim().is_empty() {
+                return Err(validator.error(definition.name.span().start, "Mod name is empty"));
+            }
+            if !mod_names.insert(definition.name.get_ref().clone()) {
+                return Err(validator.error(
+                    definition.name.span().start,
+                    format!("Duplicate mod name '{}'", definition.name.get_ref()),
+                ));
+            }
+            let (query, target_paths) = match (&definition.query, &definition.targets) {
+                (Some(query), None) => {
+                    if query.children.is_none() {
+                        return Err(validator.error(
+                            query.name.span().start,
+                            format!("Query root '{}' must have children", query.name.get_ref()),
+                        ));
+                    }
+                    (validator.convert_query(query)?, Vec::new())
+                }
+                (None, Some(targets)) => validator.convert_targets(targets)?,
+                (Some(_), Some(targets)) => {
+                    return Err(validator.error(
+                        targets.span().start,
+                        "A mod has either a query or targets, not both",
+                    ))
+                }
+                (None, None) => {
+                    return Err(validator.error(
+                        definition.name.span().start,
+                        format!(
+                            "Mod '{}' has neither a query nor targets",
+                            definition.name.get_ref()
+                        ),
+                    ))
+                }
+            };
+            let excluded_templates =
+                validator.convert_names(&definition.excluded_templates, "Template name")?;
+            let forced_guids = validator.convert_names(&definition.forced_guids, "Asset")?;
+            let excluded_guids = validator.convert_names(&definition.excluded_guids, "Asset")?;
+            let excluded_where = validator.convert_selector(definition.excluded_where.as_ref())?;
+            let forced_where = validator.convert_selector(definition.forced_where.as_ref())?;
+
+            let target_spans = definition
+                .targets
+                .iter()
+                .flat_map(|targets| targets.get_ref().iter().map(|target| target.path.span()))
+                .collect::<Vec<_>>();
+            let locations = [
+                (
+                    List::ExcludedTemplates,
+                    definition
+                        .excluded_templates
+                        .iter()
+                        .map(|entry| entry.span())
+                        .collect(),
+                ),
+                (
+                    List::ForcedGuids,
+                    definition
+                        .forced_guids
+                        .iter()
+                        .map(|entry| entry.span())
+                        .collect(),
+                ),
+                (
+                    List::ExcludedGuids,
+                    definition
+                        .excluded_guids
+                        .iter()
+                        .map(|entry| entry.span())
+                        .collect(),
+                ),
+                (List::Targets, target_spans),
+            ]
+            .into_iter()
+            .flat_map(|(list, spans): (List, Vec<_>)| {
+                let validator = &validator;
+                spans
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(index, span)| ((list, index), validator.location(span.start)))
+            })
+            .collect();
+
+            Ok(Recipe {
+                name: definition.name.into_inner(),
+                query,
+                excluded_templates,
+                forced_guids,
+                excluded_guids,
+                excluded_where,
+                forced_where,
+                target_paths,
+                modinfo: definition.modinfo,
+                source_name: source_name.to_string(),
+                locations,
+            })
+        })
+        .collect()
+}
+
+struct Validator<'a> {
+    source_name: &'a str,
+    text: &'a str,
+}
+
+impl Validator<'_> {
+    fn error(&self, offset: usize, message: impl Into<String>) -> RecipeError {
+        let (line, column) = self.location(offset);
+        RecipeError {
+            source_name: self.source_name.to_string(),
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    /// The line and column of the offset, both starting at 1.
+    fn location(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        (line, column)
+    }
+
+    fn convert_query(&self, definition: &QueryDefinition) -> Result<XmlTag, RecipeError> {
+        let step = definition.name.get_ref();
+        let (name, key) = convert_step(step).ok_or_else(|| {
+            self.error(
+                definition.name.span().start,
+                format!("'{step}' {INVALID_STEP}"),
+            )
+        })?;
+        let name = &name;
+
+        let placement = self.convert_placement(
+            name,
+            definition.insert_after.as_ref(),
+            definition.insert_before.as_ref(),
+        )?;
+
+        let (Some(children), None) = (&definition.children, &definition.action) else {
+            if let (Some(action), Some(_)) = (&definition.action, &definition.children) {
+                return Err(self.error(
+                    action.span().start,
+                    format!("'{name}' is removed, it can not have children or a transform"),
+                ));
+            }
+            let content = self.convert_leaf(
+                &definition.name,
+                definition.transform.as_ref(),
+                definition.action.as_ref(),
+            )?;
+            if key.is_some() && matches!(content, Content::Leaf(_)) {
+                return Err(self.error(definition.name.span().start, item_leaf_message(step)));
+            }
+            return Ok(XmlTag {
+                name: name.clone(),
+                key,
+                content,
+                placement,
+            });
+        };
+
+        if let Some(transforms) = &definition.transform {
+            return Err(self.error(
+                transforms.span().start,
+                format!("Branch '{name}' can not have a transform, only leaves can"),
+            ));
+        }
+
+        if children.get_ref().is_empty() {
+            return Err(self.error(
+                children.span().start,
+                format!("'{name}' has an empty children list, remove it to make '{name}' a leaf"),
+            ));
+        }
+
+        let mut converted_children: Vec<XmlTag> = Vec::new();
+        for child in children.get_ref() {
+            let converted_child = self.convert_query(child)?;
+            if let Some(message) = key_child_message(key.as_ref(), step, &converted_child.name)
+                .or_else(|| overlap_message(&converted_children, &converted_child, step))
+            {
+                return Err(self.error(child.name.span().start, message));
+            }
+            converted_children.push(converted_child);
+        }
+
+        Ok(XmlTag {
+            name: name.clone(),
+            key,
+            content: Content::Branch(converted_children),
+            placement,
+        })
+    }
+
+    /// The content of a node without children, a leaf changed by its transforms or a node that
+    /// is removed.
+    fn convert_leaf(
+        &self,
+        name: &Spanned<String>,
+        transform: Option<&Spanned<Vec<Transform>>>,
+        action: Option<&Spanned<Action>>,
+    ) -> Result<Content, RecipeError> {
+        if let Some(action) = action {
+            match action.get_ref() {
+                Action::Remove => {
+                    if transform.is_some() {
+                        return Err(self.error(
+                            action.span().start,
+                            format!(
+                                "'{}' is removed, it can not have children or a transform",
+                                name.get_ref()
+                            ),
+                        ));
+                    }
+                    return Ok(Content::Remove);
+                }
+            }
+        }
+
+        let Some(transforms) = transform else {
+            return Err(self.error(
+                name.span().start,
+                format!("Leaf '{}' has no transform", name.get_ref()),
+            ));
+        };
+        if transforms.get_ref().is_empty() {
+            return Err(self.error(
+                transforms.span().start,
+                format!("Leaf '{}' has an empty transform list", name.get_ref()),
+            ));
+        }
+        if let Some(problem) = transforms
+            .get_ref()
+            .iter()
+            .find_map(|transform| transform.validate())
+        {
+            return Err(self.error(transforms.span().start, problem));
+        }
+        Ok(Content::Leaf(transforms.get_ref().clone()))
+    }
+
+    /// Combines the paths of the targets into a query, paths sharing their start share the
+    /// nodes. The paths are returned alongside, in the order of the targets.
+    fn convert_targets(
+        &self,
+        targets: &Spanned<Vec<TargetDefinition>>,
+    ) -> Result<(XmlTag, Vec<Vec<String>>), RecipeError> {
+        let mut root: Option<XmlTag> = None;
+        let mut paths = Vec::new();
+        for target in targets.get_ref() {
+            let path_text = target.path.get_ref();
+            let path_error = |message: String| self.error(target.path.span().start, message);
+            let path = path_text.split('/').map(str::to_string).collect::<Vec<_>>();
+            let steps = path
+                .iter()
+                .map(|step| {
+                    convert_step(step).ok_or_else(|| {
+                        path_error(format!("'{step}' in '{path_text}' {INVALID_STEP}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let [(root_name, root_key), branch_steps @ .., (leaf_name, leaf_key)] =
+                steps.as_slice()
+            else {
+                return Err(path_error(format!(
+                    "'{path_text}' needs a property and a value, like 'FactoryBase/CycleTime'"
+                )));
+            };
+            if root_key.is_some() {
+                return Err(path_error(format!(
+                    "'{path_text}' starts with a list item, a mod changes a single property"
+                )));
+            }
+            if let Some(message) = steps
+                .windows(2)
+                .zip(&path)
+                .find_map(|(pair, step)| key_child_message(pair[0].1.as_ref(), step, &pair[1].0))
+            {
+                return Err(path_error(message));
+            }
+
+            let root = root.get_or_insert_with(|| XmlTag {
+                name: root_name.clone(),
+                key: None,
+                content: Content::Branch(Vec::new()),
+                placement: None,
+            });
+            if root.name != *root_name {
+                return Err(path_error(format!(
+                    "'{path_text}' does not start with '{}' like the first target, a mod changes a single property",
+                    root.name
+                )));
+            }
+
+            let mut node = root;
+            for (depth, (name, key)) in branch_steps.iter().enumerate() {
+                let Content::Branch(children) = &mut node.content else {
+                    unreachable!("only branches are descended into");
+                };
+                let position = match children
+                    .iter()
+                    .position(|child| child.name == *name && child.key == *key)
+                {
+                    Some(position) => position,
+                    None => {
+                        let child = XmlTag {
+                            name: name.clone(),
+                            key: key.clone(),
+                            content: Content::Branch(Vec::new()),
+                            placement: None,
+                        };
+                        if let Some(message) = overlap_message(children, &child, &path[depth]) {
+                            return Err(path_error(message));
+                        }
+                        children.push(child);
+                        children.len() - 1
+                    }
+                };
+                node = &mut children[position];
+                if !matches!(node.content, Content::Branch(_)) {
+                    return Err(path_error(format!(
+                        "'{}' is changed by another target, it can not have children",
+                        path[..depth + 2].join("/")
+                    )));
+                }
+            }
+
+            let Content::Branch(children) = &mut node.content else {
+                unreachable!("only branches are descended into");
+            };
+            if children
+                .iter()
+                .any(|child| child.name == *leaf_name && child.key == *leaf_key)
+            {
+                return Err(path_error(format!(
+                    "'{path_text}' appears more than once or has children"
+                )));
+            }
+            let content = self.convert_leaf(
+                &target.path,
+                target.transform.as_ref(),
+                target.action.as_ref(),
+            )?;
+            if leaf_key.is_some() && matches!(content, Content::Leaf(_)) {
+                return Err(path_error(item_leaf_message(&path[path.len() - 1])));
+            }
+            let leaf = XmlTag {
+                name: leaf_name.clone(),
+                key: leaf_key.clone(),
+                content,
+                placement: self.convert_placement(
+                    leaf_name,
+                    target.insert_after.as_ref(),
+                    target.insert_before.as_ref(),
+                )?,
+            };
+            if let Some(message) = overlap_message(children, &leaf, &path[path.len() - 2]) {
+                return Err(path_error(message));
+            }
+            children.push(leaf);
+            paths.push(path);
+        }
+
+        match root {
+            Some(root) => Ok((root, paths)),
+            None => Err(self.error(targets.span().start, "The list of targets is empty")),
+        }
+    }
+
+    fn convert_placement(
+        &self,
+        name: &str,
+        insert_after: Option<&Spanned<String>>,
+        insert_before: Option<&Spanned<String>>,
+    ) -> Result<Option<Placement>, RecipeError> {
+        let (sibling, placement) = match (insert_after, insert_before) {
+            (None, None) => return Ok(None),
+            (Some(after), None) => (after, Placement::After(after.get_ref().clone())),
+            (None, Some(before)) => (before, Placement::Before(before.get_ref().clone())),
+            (Some(_), Some(before)) => {
+                return Err(self.error(
+                    before.span().start,
+                    format!("'{name}' can not have both insert_after and insert_before"),
+                ))
+            }
+        };
+        match is_valid_tag_name(sibling.get_ref()) {
+            true => Ok(Some(placement)),
+            false => Err(self.error(
+                sibling.span().start,
+                format!("'{}' is not a valid tag name", sibling.get_ref()),
+            )),
+        }
+    }
+
+    fn convert_selector(
+        &self,
+        selector: Option<&Spanned<String>>,
+    ) -> Result<Option<Selector>, RecipeError> {
+        selector
+            .map(|selector| {
+                // The offset of the problem is within the string, after its opening quotes.
+                let start = selector.span().start;
+                let quotes = match self.text.get(start..start + 3) {
+                    Some("\"\"\"" | "'''") => 3,
+                    _ => 1,
+                };
+                Selector::parse(selector.get_ref())
+                    .map_err(|error| self.error(start + quotes + error.offset, error.message))
+            })
+            .transpose()
+    }
+
+    fn convert_names(
+        &self,
+        names: &[Spanned<String>],
+        kind: &str,
+    ) -> Result<Vec<String>, RecipeError> {
+        names
+            .iter()
+            .map(|name| match name.get_ref().trim().is_empty() {
+                true => Err(self.error(name.span().start, format!("{kind} is empty"))),
+                false => Ok(name.get_ref().trim().to_string()),
+            })
+            .collect()
+    }
+}
+
+impl Recipe {
+    /// Replaces the names of assets in `forced_guids` and `excluded_guids` with their GUIDs and
+    /// checks that the listed GUIDs and templates exist and that the DefaultValues have the
+    /// paths of the targets.
+    ///
+    /// A name is matched against the texts and the `Standard/Name` of the assets, ignoring case.
+    /// Names that match no asset or more than one are left out and returned as problems, GUIDs
+    /// and templates that do not exist are kept and returned as warnings. Targets missing in
+    /// the DefaultValues are problems, as they would never change anything.
+    pub(crate) fn resolve(
+        &mut self,
+        database: &AssetDatabase,
+    ) -> (Vec<RecipeError>, Vec<RecipeError>) {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        // The entries found in the game data, by their list, index and name in the recipe.
+        let mut listed = Vec::new();
+
+        for (index, template) in self.excluded_templates.iter().enumerate() {
+            match database
+                .find(template)
+                .is_some_and(|index| database.entries[index].node_type == NodeType::Template)
+            {
+                true => listed.push((
+                    List::ExcludedTemplates,
+                    index,
+                    template.clone(),
+                    template.clone(),
+                )),
+                false => warnings.push(self.error(
+                    List::ExcludedTemplates,
+                    index,
+                    format!("No template named '{template}', excluding it has no effect"),
+                )),
+            }
+        }
+
+        // Only built if a name is listed, the game has many thousand assets.
+        let mut names: Option<HashMap<String, BTreeSet<&str>>> = None;
+        for list in [List::ForcedGuids, List::ExcludedGuids] {
+            let entries = match list {
+                List::ForcedGuids => std::mem::take(&mut self.forced_guids),
+                _ => std::mem::take(&mut self.excluded_guids),
+            };
+            let mut guids = Vec::new();
+            for (index, entry) in entries.into_iter().enumerate() {
+                if entry.chars().all(|c| c.is_ascii_digit()) {
+                    match database
+                        .find(&entry)
+                        .is_some_and(|index| database.entries[index].node_type == NodeType::Asset)
+                    {
+                        true => listed.push((list, index, entry.clone(), entry.clone())),
+                        false => warnings.push(self.error(
+                            list,
+                            index,
+                            format!("No asset with the GUID '{entry}', listing it has no effect"),
+                        )),
+                    }
+                    guids.push(entry);
+                    continue;
+                }
+
+                let names = names.get_or_insert_with(|| {
+                    let mut names: HashMap<String, BTreeSet<&str>> = HashMap::new();
+                    database.asset_names().for_each(|(name, guid)| {
+                        names.entry(name.to_lowercase()).or_default().insert(guid);
+                    });
+                    names
+                });
+                match names.get(&entry.to_lowercase()) {
+                    Some(matches) if matches.len() == 1 => {
+                        let guid = matches.iter().next().unwrap().to_string();
+                        listed.push((list, index, entry.clone(), guid.clone()));
+                        guids.push(guid);
+                    }
+                    Some(matches) => errors.push(self.error(
+                        list,
+                        index,
+                        format!(
+                            "'{entry}' is the name of several assets, use one of their GUIDs: {}",
+                            matches.iter().map(|guid| guid.to_string()).collect::<Vec<_>>().join(", ")
+                        ),
+                    )),
+                    None => {
+                        let candidates = names.keys().map(String::as_str);
+                        let suggestions = closest_names(&entry.to_lowercase(), candidates)
+                            .into_iter()
+                            .flat_map(|name| {
+                                names[name].iter().map(|guid| {
+                                    let name = database.name_of(guid).unwrap_or(name);
+                                    format!("'{name}' ({guid})")
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        let message = match suggestions.is_empty() {
+                            true => format!("No asset named '{entry}'"),
+                            false => format!(
+                                "No asset named '{entry}', did you mean {}?",
+                                suggestions.join(", ")
+                            ),
+                        };
+                        errors.push(self.error(list, index, message));
+                    }
+                }
+            }
+            match list {
+                List::ForcedGuids => self.forced_guids = guids,
+                _ => self.excluded_guids = guids,
+            }
+        }
+
+        errors.extend(
+            (0..self.target_paths.len()).filter_map(|index| self.check_target(database, index)),
+        );
+
+        // An entry that exists but does not have the query, like a factory excluded from a
+        // recipe for residences, has no effect either. It has the query if its template has, like
+        // the mod builder decides. Nothing is part of the mod if the query is missing from the
+        // DefaultValues, which is reported on its own.
+        let query = &self.query.name;
+        let has_query = |entry: &Entry| {
+            entry
+                .properties
+                .as_ref()
+                .is_some_and(|properties| helper::has_direct_child(properties, query))
+        };
+        let in_default_values = database
+            .entries
+            .iter()
+            .take_while(|entry| entry.node_type == NodeType::DefaultValues)
+            .any(has_query);
+        if in_default_values {
+            let step = self.query.step();
+            for (list, index, entry, name) in listed {
+                let Some(found) = database.find(&name).map(|index| &database.entries[index]) else {
+                    continue;
+                };
+                let template = match found.node_type {
+                    NodeType::Template => Some(found),
+                    _ => database.template_of(found),
+                };
+                if template.is_some_and(has_query) {
+                    continue;
+                }
+                let message = match list {
+                    List::ExcludedTemplates => {
+                        format!("Template '{entry}' has no '{step}', excluding it has no effect")
+                    }
+                    _ => format!("Asset '{entry}' has no '{step}', listing it has no effect"),
+                };
+                warnings.push(self.error(list, index, message));
+            }
+        }
+
+        // The lists are checked one after the other, the warnings follow the recipe.
+        warnings.sort_by_key(|warning| (warning.line, warning.column));
+        (errors, warnings)
+    }
+
+    /// The problem with a target whose path is in none of the DefaultValues, with the names
+    /// closest to the first part of the path that is missing. The path is only followed into
+    /// the items of a list if the DefaultValues have one.
+    fn check_target(&self, database: &AssetDatabase, index: usize) -> Option<RecipeError> {
+        let path = &self.target_paths[index];
+        let default_values = database
+            .entries
+            .iter()
+            .take_while(|entry| entry.node_type == NodeType::DefaultValues)
+            .filter_map(|entry| entry.properties.as_ref())
+            .collect::<Vec<_>>();
+
+        // The nodes at the deepest level of the path any DefaultValues reach.
+        let mut nodes = default_values;
+        for (depth, step) in path.iter().enumerate() {
+            let (name, key) = xml_structure::parse_step(step)?;
+            let children = nodes
+                .iter()
+                .flat_map(|node| node.children_named(name))
+                .collect::<Vec<_>>();
+            if !children.is_empty() {
+                nodes = children;
+                continue;
+            }
+            // The DefaultValues of a list usually have no items.
+            if key.is_some() {
+                return None;
+            }
+
+            let missing = match depth {
+                0 => format!("no DefaultValues have '{name}'"),
+                _ => format!("'{}' has no '{name}'", path[..depth].join("/")),
+            };
+            let candidates = nodes
+                .iter()
+                .flat_map(|node| &node.children)
+                .map(|child| child.name.as_str());
+            let suggestions = closest_names(name, candidates)
+                .into_iter()
+                .map(|candidate| format!("'{candidate}'"))
+                .collect::<Vec<_>>();
+            let message = match suggestions.is_empty() {
+                true => format!(
+                    "'{}' is not in the DefaultValues, {missing}",
+                    path.join("/")
+                ),
+                false => format!(
+                    "'{}' is not in the DefaultValues, {missing}, did you mean {}?",
+                    path.join("/"),
+                    suggestions.join(", ")
+                ),
+            };
+            return Some(self.error(List::Targets, index, message));
+        }
+        None
+    }
+
+    fn error(&self, list: List, index: usize, message: String) -> RecipeError {
+        let (line, column) = self
+            .locations
+            .get(&(list, index))
+            .copied()
+            .unwrap_or((1, 1));
+        RecipeError {
+            source_name: self.source_name.clone(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// The candidates similar enough to the name to be suggested instead, the most similar first.
+fn closest_names<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut candidates = candidates
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(similarity, _)| *similarity >= SUGGESTION_THRESHOLD)
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(b.1)));
+    candidates
+        .into_iter()
+        .take(SUGGESTION_COUNT)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+fn is_valid_tag_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Splits a node of a query or a path into its tag name and, for the items of a list, the key
+/// selecting them: `Item[Product=1010017]`, or `Item[Product=*]` for every item.
+fn convert_step(step: &str) -> Option<(String, Option<ItemKey>)> {
+    let (name, key) = xml_structure::parse_step(step)?;
+    if !is_valid_tag_name(name) {
+        return None;
+    }
+    let key = match key {
+        None => None,
+        Some((child, value)) => {
+            if !is_valid_tag_name(child) || value.is_empty() {
+                return None;
+            }
+            Some(ItemKey {
+                child: child.to_string(),
+                value: (value != "*").then(|| value.to_string()),
+            })
+        }
+    };
+    Some((name.to_string(), key))
+}
+
+fn item_leaf_message(step: &str) -> String {
+    format!("'{step}' is a list item, it needs children or to be removed")
+}
+
+/// The problem with a child changing the key its parent selects the items of a list by, which
+/// would leave the ModOps unable to find the items.
+fn key_child_message(key: Option<&ItemKey>, step: &str, child_name: &str) -> Option<String> {
+    key.filter(|key| key.child == child_name)
+        .map(|_| format!("'{child_name}' selects the items of '{step}', it can not be changed"))
+}
+
+/// The problem with a child selecting nodes another child of the same parent selects as well.
+fn overlap_message(children: &[XmlTag], child: &XmlTag, parent_step: &str) -> Option<String> {
+    let other = children.iter().find(|other| other.overlaps(child))?;
+    Some(match other.step() == child.step() {
+        true => format!(
+            "'{}' appears more than once in '{parent_step}'",
+            child.step()
+        ),
+        false => format!(
+            "'{}' and '{}' can select the same items in '{parent_step}'",
+            other.step(),
+            child.step()
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROPERTIES: &str = r#"<Properties><DefaultValues>
+  <Standard><GUID>0</GUID><Name></Name></Standard>
+  <Residence7><Capacity>1</Capacity><Needs /></Residence7>
+  <FactoryBase><CycleTime>30</CycleTime></FactoryBase>
+</DefaultValues></Properties>"#;
+
+    const TEMPLATES: &str = r#"<Templates>
+  <Template><Name>ResidenceBuilding7</Name><Properties><Standard /><Residence7 /></Properties></Template>
+  <Template><Name>FactoryBuilding7</Name><Properties><Standard /><FactoryBase /></Properties></Template>
+</Templates>"#;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Asset><Template>ResidenceBuilding7</Template><Values>
+    <Standard><GUID>1</GUID><Name>Farmer Residence</Name></Standard>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>2</GUID><Name>Sawmill</Name></Standard>
+  </Values></Asset>
+</AssetList>"#;
+
+    fn database() -> AssetDatabase {
+        AssetDatabase::from_documents(PROPERTIES, TEMPLATES, ASSETS, &[])
+    }
+
+    fn messages(errors: &[RecipeError]) -> Vec<String> {
+        errors.iter().map(|error| error.to_string()).collect()
+    }
+
+    /// The targets as written in a recipe, one per line starting at line 4.
+    fn parse_targets(targets: &[&str]) -> Result<Recipe, String> {
+        let text = format!(
+            "[[mod]]\nname = \"Targets\"\ntargets = [\n{}\n]\n",
+            targets.join(",\n")
+        );
+        parse_recipes("test", &text)
+            .map(|mut recipes| recipes.remove(0))
+            .map_err(|error| error.to_string())
+    }
+
+    /// The query in short, `-` in front of removed nodes and the children of branches in
+    /// parentheses.
+    fn tree(tag: &XmlTag) -> String {
+        match &tag.content {
+            Content::Branch(children) => format!(
+                "{}({})",
+                tag.step(),
+                children.iter().map(tree).collect::<Vec<_>>().join(", ")
+            ),
+            Content::Leaf(_) => tag.step(),
+            Content::Remove => format!("-{}", tag.step()),
+        }
+    }
+
+    #[test]
+    fn combines_targets_into_a_query() {
+        let recipe = parse_targets(&[
+            r#"{ path = "Transporter7/TransporterSpeed", transform = [{ multiply = 30 }] }"#,
+            r#"{ path = "Transporter7/ProcessingTimes/LoadingTime", transform = ["ceil"] }"#,
+            r#"{ path = "Transporter7/ProcessingTimes/UnloadingTime", transform = ["ceil"] }"#,
+            r#"{ path = "Transporter7/Extra/Deep/Node", action = "remove" }"#,
+        ])
+        .unwrap();
+        assert_eq!(
+            tree(&recipe.query),
+            "Transporter7(TransporterSpeed, ProcessingTimes(LoadingTime, UnloadingTime), \
+             Extra(Deep(-Node)))"
+        );
+        assert_eq!(
+            recipe.target_paths,
+            [
+                vec!["Transporter7", "TransporterSpeed"],
+                vec!["Transporter7", "ProcessingTimes", "LoadingTime"],
+                vec!["Transporter7", "ProcessingTimes", "UnloadingTime"],
+                vec!["Transporter7", "Extra", "Deep", "Node"],
+            ]
+        );
+    }
+
+    #[test]
+    fn combines_targets_with_keyed_steps() {
+        let recipe = parse_targets(&[
+            r#"{ path = "Maintenance/Maintenances/Item[Product=1010017]/Amount", transform = [{ divide = 2 }] }"#,
+            r#"{ path = "Maintenance/Maintenances/Item[Product='1010017']/InactiveAmount", transform = [{ divide = 2 }] }"#,
+            r#"{ path = "Maintenance/Maintenances/Item[Product=1010018]", action = "remove" }"#,
+            r#"{ path = "Maintenance/Inputs/Item[Product=*]/Amount", transform = [{ set = 1 }] }"#,
+        ])
+        .unwrap();
+        assert_eq!(
+            tree(&recipe.query),
+            "Maintenance(Maintenances(Item[Product=1010017](Amount, InactiveAmount), \
+             -Item[Product=1010018]), Inputs(Item[Product=*](Amount)))"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_targets_at_their_path() {
+        let cases: [(&[&str], &str); 13] = [
+            (
+                &[r#"{ path = "Factory Base/CycleTime", transform = ["ceil"] }"#],
+                "test:4:10: 'Factory Base' in 'Factory Base/CycleTime' is not a valid tag name \
+                 or list item like 'Item[Product=1010017]'",
+            ),
+            (
+                &[r#"{ path = "A/Item[Product]/B", transform = ["ceil"] }"#],
+                "test:4:10: 'Item[Product]' in 'A/Item[Product]/B' is not a valid tag name or \
+                 list item like 'Item[Product=1010017]'",
+            ),
+            (
+                &[r#"{ path = "FactoryBase", transform = ["ceil"] }"#],
+                "test:4:10: 'FactoryBase' needs a property and a value, like \
+                 'FactoryBase/CycleTime'",
+            ),
+            (
+                &[r#"{ path = "Item[Product=1]/Amount", transform = ["ceil"] }"#],
+                "test:4:10: 'Item[Product=1]/Amount' starts with a list item, a mod changes a \
+                 single property",
+            ),
+            (
+                &[r#"{ path = "A/Item[Product=1]/Product", transform = ["ceil"] }"#],
+                "test:4:10: 'Product' selects the items of 'Item[Product=1]', it can not be \
+                 changed",
+            ),
+            (
+                &[r#"{ path = "A/Item[Product=1]", transform = ["ceil"] }"#],
+                "test:4:10: 'Item[Product=1]' is a list item, it needs children or to be removed",
+            ),
+            (
+                &[
+                    r#"{ path = "A/B", transform = ["ceil"] }"#,
+                    r#"{ path = "C/B", transform = ["ceil"] }"#,
+                ],
+                "test:5:10: 'C/B' does not start with 'A' like the first target, a mod changes \
+                 a single property",
+            ),
+            (
+                &[
+                    r#"{ path = "A/B", transform = ["ceil"] }"#,
+                    r#"{ path = "A/B", transform = ["floor"] }"#,
+                ],
+                "test:5:10: 'A/B' appears more than once or has children",
+            ),
+            (
+                &[
+                    r#"{ path = "A/B", transform = ["ceil"] }"#,
+                    r#"{ path = "A/B/C", transform = ["ceil"] }"#,
+                ],
+                "test:5:10: 'A/B' is changed by another target, it can not have children",
+            ),
+            (
+                &[
+                    r#"{ path = "A/B/C", transform = ["ceil"] }"#,
+                    r#"{ path = "A/B", transform = ["ceil"] }"#,
+                ],
+                "test:5:10: 'A/B' appears more than once or has children",
+            ),
+            (
+                &[
+                    r#"{ path = "A/L/Item[Product=1]/B", transform = ["ceil"] }"#,
+                    r#"{ path = "A/L/Item[Product=*]/C", transform = ["ceil"] }"#,
+                ],
+                "test:5:10: 'Item[Product=1]' and 'Item[Product=*]' can select the same items \
+                 in 'L'",
+            ),
+            (
+                &[
+                    r#"{ path = "A/L/Item[Product=1]", action = "remove" }"#,
+                    r#"{ path = "A/L/Item", action = "remove" }"#,
+                ],
+                "test:5:10: 'Item[Product=1]' and 'Item' can select the same items in 'L'",
+            ),
+            (&[], "test:3:11: The list of targets is empty"),
+        ];
+        for (targets, message) in cases {
+            assert_eq!(
+                parse_targets(targets).map(|recipe| tree(&recipe.query)),
+                Err(message.to_string()),
+                "{targets:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_targets_next_to_a_query() {
+        let error = parse_recipes(
+            "test",
+            r#"[[mod]]
+name = "Both"
+query = { name = "A", children = [{ name = "B", transform = ["ceil"] }] }
+targets = [{ path = "A/C", transform = ["ceil"] }]
+"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test:4:11: A mod has either a query or targets, not both"
+        );
+    }
+
+    #[test]
+    fn checks_targets_against_the_default_values() {
+        let mut recipe = parse_targets(&[
+            r#"{ path = "Residence7/Capacity", transform = [{ set = 2 }] }"#,
+            r#"{ path = "Residence7/Capacty", transform = [{ set = 2 }] }"#,
+            r#"{ path = "Residence7/Needs/Item[Product=1]/Amount", transform = [{ set = 2 }] }"#,
+            r#"{ path = "Residence7/Nedes/Item[Product=2]/Amount", transform = [{ set = 2 }] }"#,
+            r#"{ path = "Residence7/Upgrade/Cost", transform = [{ set = 2 }] }"#,
+        ])
+        .unwrap();
+        let (errors, _) = recipe.resolve(&database());
+        assert_eq!(
+            messages(&errors),
+            [
+                "test:5:10: 'Residence7/Capacty' is not in the DefaultValues, 'Residence7' has no \
+                 'Capacty', did you mean 'Capacity'?",
+                "test:7:10: 'Residence7/Nedes/Item[Product=2]/Amount' is not in the \
+                 DefaultValues, 'Residence7' has no 'Nedes', did you mean 'Needs'?",
+                "test:8:10: 'Residence7/Upgrade/Cost' is not in the DefaultValues, 'Residence7' \
+                 has no 'Upgrade'",
+            ]
+        );
+
+        let mut recipe =
+            parse_targets(&[r#"{ path = "Residenc7/Capacity", action = "remove" }"#]).unwrap();
+        let (errors, _) = recipe.resolve(&database());
+        assert_eq!(
+            messages(&errors),
+            [
+                "test:4:10: 'Residenc7/Capacity' is not in the DefaultValues, no DefaultValues \
+              have 'Residenc7', did you mean 'Residence7'?"
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_listed_entries_that_are_not_part_of_the_mod() {
+        let mut recipes = parse_recipes(
+            "test",
+            r#"[[mod]]
+name = "Residence"
+query = { name = "Residence7", children = [{ name = "Capacity", transform = [{ set = 2 }] }] }
+excluded_templates = ["ResidenceBuilding7", "FactoryBuilding7", "Mall"]
+excluded_guids = ["1", "2", "3"]
+forced_guids = ["sawmill", "Farmer Residence"]
+"#,
+        )
+        .unwrap();
+        let (errors, warnings) = recipes[0].resolve(&database());
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            messages(&warnings),
+            [
+                "test:4:45: Template 'FactoryBuilding7' has no 'Residence7', excluding it has no effect",
+                "test:4:65: No template named 'Mall', excluding it has no effect",
+                "test:5:24: Asset '2' has no 'Residence7', listing it has no effect",
+                "test:5:29: No asset with the GUID '3', listing it has no effect",
+                "test:6:17: Asset 'sawmill' has no 'Residence7', listing it has no effect",
+            ]
+        );
+        assert_eq!(recipes[0].forced_guids, ["2", "1"]);
+    }
+
+    #[test]
+    fn orders_the_warnings_like_the_recipe() {
+        let mut recipes = parse_recipes(
+            "test",
+            r#"[[mod]]
+name = "Residence"
+query = { name = "Residence7", children = [{ name = "Capacity", transform = [{ set = 2 }] }] }
+excluded_templates = ["FactoryBuilding7", "Nope"]
+excluded_guids = ["2", "424242"]
+"#,
+        )
+        .unwrap();
+        let (errors, warnings) = recipes[0].resolve(&database());
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            messages(&warnings),
+            [
+                "test:4:23: Template 'FactoryBuilding7' has no 'Residence7', excluding it has no effect",
+                "test:4:43: No template named 'Nope', excluding it has no effect",
+                "test:5:19: Asset '2' has no 'Residence7', listing it has no effect",
+                "test:5:24: No asset with the GUID '424242', listing it has no effect",
+            ]
+        );
+    }
+
+    #[test]
+    fn suggests_the_closest_names_of_unknown_assets() {
+        let mut recipes = parse_recipes(
+            "test",
+            r#"[[mod]]
+name = "Residence"
+query = { name = "Residence7", children = [{ name = "Capacity", transform = [{ set = 2 }] }] }
+forced_guids = ["Farmer Residenc", "Sawmil", "Fisher"]
+"#,
+        )
+        .unwrap();
+        let (errors, _) = recipes[0].resolve(&database());
+        assert_eq!(
+            messages(&errors),
+            [
+                "test:4:17: No asset named 'Farmer Residenc', did you mean 'Farmer Residence' (1)?",
+                "test:4:36: No asset named 'Sawmil', did you mean 'Sawmill' (2)?",
+                "test:4:46: No asset named 'Fisher'",
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    database::{AssetDatabase, NodeType},
+    helper::{self, ModFiles, ModOpsKind, ModOpsStructure},
+    inspect,
+    mod_builder::ModEntry,
+    recipe::Recipe,
+    xml_node::{XmlNode, XmlNodeData},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+/// What writing the mod of a recipe would do, printed by a dry run.
+#[derive(Debug, Serialize)]
+pub(crate) struct ModReport {
+    name: String,
+    /// The directory that would be replaced.
+    directory: String,
+    /// The number of changed assets by the name of their template.
+    assets_per_template: BTreeMap<String, usize>,
+    changes: Vec<Change>,
+    files: Vec<String>,
+}
+
+/// A value the mod changes, `None` where the node is missing.
+#[derive(Debug, Serialize)]
+struct Change {
+    /// The GUID of an asset, the name of a template, or the XPath of the entry.
+    entry: String,
+    /// The name of the asset, if it has one.
+    name: Option<String>,
+    path: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl Change {
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} ({name})", self.entry),
+            None => self.entry.clone(),
+        }
+    }
+}
+
+/// Describes the changes of the mod entries and the files the mod would be written to.
+pub(crate) fn plan(
+    output_path: &Path,
+    recipe: &Recipe,
+    database: &AssetDatabase,
+    mod_entries: &[ModEntry],
+    mod_files: &ModFiles,
+) -> ModReport {
+    let mut assets_per_template = BTreeMap::new();
+    let mut changes = Vec::new();
+    for mod_entry in mod_entries {
+        // Entries whose ModOps could not be created are already reported as problems.
+        let Ok(structure) =
+            helper::create_mod_ops_structure(&mod_entry.content, &recipe.query, &mod_entry.state)
+        else {
+            continue;
+        };
+        let mut entry_changes = Vec::new();
+        collect_changes(
+            &mod_entry.content,
+            &structure,
+            &mut Vec::new(),
+            &mut entry_changes,
+        );
+        if entry_changes.is_empty() {
+            continue;
+        }
+
+        let entry = mod_entry.entry;
+        if entry.node_type == NodeType::Asset {
+            let template = database
+                .template_of(entry)
+                .map(|template| template.identifier.value.clone())
+                .unwrap_or_else(|| "(no template)".to_string());
+            *assets_per_template.entry(template).or_default() += 1;
+        }
+        let label = inspect::label(entry);
+        let name = database.name(entry).map(str::to_string);
+        changes.extend(entry_changes.into_iter().map(|(path, old, new)| Change {
+            entry: label.clone(),
+            name: name.clone(),
+            path,
+            old,
+            new,
+        }));
+    }
+
+    let mod_path = helper::mod_path(output_path, recipe);
+    let mut files = mod_files
+        .path_vs_mod_ops
+        .keys()
+        .map(|path| mod_path.join(path.to_path_buf()))
+        .chain([mod_path.join("modinfo.json")])
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    ModReport {
+        name: recipe.name.clone(),
+        directory: mod_path.display().to_string(),
+        assets_per_template,
+        changes,
+        files,
+    }
+}
+
+/// The old and new values of every node the ModOps change, with the path of the node.
+fn collect_changes(
+    content: &XmlNode,
+    structure: &ModOpsStructure,
+    path: &mut Vec<String>,
+    changes: &mut Vec<(String, Option<String>, Option<String>)>,
+) {
+    path.push(structure.name.clone());
+    // A removed node is handled as a whole, only whether it is there is shown.
+    let old = match &content.data {
+        XmlNodeData::Leaf(value) => Some(value.to_string()),
+        XmlNodeData::Raw(_) => Some("present".to_string()),
+        _ => None,
+    };
+    let new = match (&structure.kind, &content.data) {
+        (ModOpsKind::ReplaceValue(_) | ModOpsKind::AddValue(_), XmlNodeData::Raw(_)) => {
+            Some(Some("present".to_string()))
+        }
+        (ModOpsKind::ReplaceValue(value) | ModOpsKind::AddValue(value), _) => {
+            Some(Some(value.clone()))
+        }
+        (ModOpsKind::Remove, _) => Some(None),
+        _ => None,
+    };
+    if let Some(new) = new {
+        changes.push((path.join("/"), old, new));
+    }
+
+    if let XmlNodeData::Branch(children) = &content.data {
+        structure.children.iter().for_each(|child_structure| {
+            if let Some(child) = children
+                .iter()
+                .find(|child| child.name == child_structure.name)
+            {
+                collect_changes(child, child_structure, path, changes);
+            }
+        });
+    }
+    path.pop();
+}
+
+/// The reports of all mods as text.
+pub(crate) fn to_text(reports: &[ModReport]) -> String {
+    let mut text = String::new();
+    for report in reports {
+        let _ = writeln!(text, "{} ({})", report.name, report.directory);
+
+        let _ = writeln!(text, "  Changed assets per template:");
+        let width = report
+            .assets_per_template
+            .keys()
+            .map(|template| template.len())
+            .max()
+            .unwrap_or_default();
+        report
+            .assets_per_template
+            .iter()
+            .for_each(|(template, count)| {
+                let _ = writeln!(text, "    {template:<width$}  {count}");
+            });
+
+        let _ = writeln!(text, "  Changes:");
+        let entry_width = report
+            .changes
+            .iter()
+            .map(|change| change.label().len())
+            .max()
+            .unwrap_or_default();
+        let path_width = report
+            .changes
+            .iter()
+            .map(|change| change.path.len())
+            .max()
+            .unwrap_or_default();
+        report.changes.iter().for_each(|change| {
+            let values = match (&change.old, &change.new) {
+                (old, new) if old == new => format!("{} (kept)", display(old)),
+                (old, new) => format!("{} -> {}", display(old), display(new)),
+            };
+            let _ = writeln!(
+                text,
+                "    {:<entry_width$}  {:<path_width$}  {values}",
+                change.label(),
+                change.path
+            );
+        });
+
+        let _ = writeln!(text, "  Files:");
+        report.files.iter().for_each(|file| {
+            let _ = writeln!(text, "    {file}");
+        });
+    }
+    text
+}
+
+pub(crate) fn to_json(reports: &[ModReport]) -> String {
+    serde_json::to_string_pretty(reports).unwrap()
+}
+
+fn display(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
@@ -0,0 +1,670 @@
+use crate::{database::AssetDatabase, source::Source};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// A condition on assets, used by recipes to exclude or force the assets matching it.
+///
+/// ```text
+/// template in ["Heater_Arctic", "Mall"] or region contains "Arctic"
+/// not exists Electric and FactoryBase/CycleTime > 120
+/// source = "dlc03"
+/// ```
+///
+/// Values are the ones the asset ends up with, set by itself or inherited from its ancestors, its
+/// template and the DefaultValues.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Selector {
+    And(Vec<Selector>),
+    Or(Vec<Selector>),
+    Not(Box<Selector>),
+    /// The asset's template is one of the names.
+    Template(Vec<String>),
+    /// The asset comes from the base game (`base`), a DLC folder (`dlc03`) or a mod (its ModID).
+    Source(String),
+    /// The asset has the node at the path.
+    Exists(Vec<String>),
+    /// The `;`-separated list at the path contains the item, like `Arctic` in
+    /// `Building/AssociatedRegions`.
+    Contains(Vec<String>, String),
+    /// The value at the path compares to the operand, false if there is no value.
+    Compare(Vec<String>, Comparison, Operand),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Operand {
+    Number(Decimal),
+    Text(String),
+}
+
+/// A problem in a selector, with the byte offset in the selector it was found at.
+#[derive(Debug)]
+pub(crate) struct SelectorError {
+    pub(crate) offset: usize,
+    pub(crate) message: String,
+}
+
+/// The path `region` is short for.
+const REGION_PATH: [&str; 2] = ["Building", "AssociatedRegions"];
+
+impl Selector {
+    pub(crate) fn parse(text: &str) -> Result<Selector, SelectorError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            end: text.len(),
+        };
+        let selector = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(selector),
+            Some((offset, token)) => Err(SelectorError {
+                offset,
+                message: format!("Expected 'and' or 'or' instead of {token}"),
+            }),
+        }
+    }
+
+    /// Whether the entry of the database at the index matches.
+    pub(crate) fn matches(&self, database: &AssetDatabase, index: usize) -> bool {
+        let entry = &database.entries[index];
+        match self {
+            Selector::And(selectors) => selectors
+                .iter()
+                .all(|selector| selector.matches(database, index)),
+            Selector::Or(selectors) => selectors
+                .iter()
+                .any(|selector| selector.matches(database, index)),
+            Selector::Not(selector) => !selector.matches(database, index),
+            Selector::Template(names) => database
+                .template_of(entry)
+                .is_some_and(|template| names.contains(&template.identifier.value)),
+            Selector::Source(name) => match &entry.origin.source {
+                Source::BaseGame => name == "base",
+                Source::Dlc(dlc) => dlc == name,
+                Source::Mod(mod_id) => mod_id == name,
+            },
+            Selector::Exists(path) => database.effective_node(index, path).is_some(),
+            Selector::Contains(path, item) => value(database, index, path)
+                .is_some_and(|value| value.split(';').any(|part| part.trim() == item)),
+            Selector::Compare(path, comparison, operand) => {
+                let Some(value) = value(database, index, path) else {
+                    return false;
+                };
+                match operand {
+                    Operand::Number(number) => Decimal::from_str(value)
+                        .or_else(|_| Decimal::from_scientific(value))
+                        .is_ok_and(|value| comparison.holds(value.cmp(number))),
+                    Operand::Text(text) => comparison.holds(value.cmp(text.as_str())),
+                }
+            }
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+fn value<'a>(database: &'a AssetDatabase, index: usize, path: &[String]) -> Option<&'a str> {
+    database
+        .effective_node(index, path)?
+        .text
+        .as_deref()
+        .map(str::trim)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(Decimal),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{word}'"),
+            Token::Text(text) => write!(f, "\"{text}\""),
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Symbol(symbol) => write!(f, "'{symbol}'"),
+        }
+    }
+}
+
+/// The symbols, longer ones first so `<=` is not read as `<`.
+const SYMBOLS: [&str; 11] = ["!=", "<=", ">=", "=", "<", ">", "(", ")", "[", "]", ","];
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, SelectorError> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let first = rest.chars().next().unwrap_or_default();
+        if first.is_whitespace() {
+            offset += first.len_utf8();
+            continue;
+        }
+
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push((offset, Token::Symbol(symbol)));
+            offset += symbol.len();
+        } else if first == '"' || first == '\'' {
+            let Some(length) = rest[1..].find(first) else {
+                return Err(SelectorError {
+                    offset,
+                    message: "Text is not closed".to_string(),
+                });
+            };
+            tokens.push((offset, Token::Text(rest[1..1 + length].to_string())));
+            offset += length + 2;
+        } else if first.is_ascii_digit() || first == '-' || first == '.' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '-' || c == '.'))
+                .unwrap_or(rest.len());
+            let number = Decimal::from_str(&rest[..length]).map_err(|_| SelectorError {
+                offset,
+                message: format!("'{}' is not a number", &rest[..length]),
+            })?;
+            tokens.push((offset, Token::Number(number)));
+            offset += length;
+        } else if first.is_ascii_alphabetic() || first == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '/'))
+                .unwrap_or(rest.len());
+            tokens.push((offset, Token::Word(rest[..length].to_string())));
+            offset += length;
+        } else {
+            return Err(SelectorError {
+                offset,
+                message: format!("Unexpected '{first}'"),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// The offset of the end of the selector, where a missing token is reported.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens
+            .get(self.position)
+            .map(|(offset, token)| (*offset, token))
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(usize, Token), SelectorError> {
+        let Some(token) = self.tokens.get(self.position).cloned() else {
+            return Err(SelectorError {
+                offset: self.end,
+                message: format!("Expected {expected} at the end"),
+            });
+        };
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Consumes the word or symbol if it comes next.
+    fn accept(&mut self, expected: &str) -> bool {
+        let is_next = match self.peek() {
+            Some((_, Token::Word(word))) => word == expected,
+            Some((_, Token::Symbol(symbol))) => *symbol == expected,
+            _ => false,
+        };
+        if is_next {
+            self.position += 1;
+        }
+        is_next
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), SelectorError> {
+        if self.accept(expected) {
+            return Ok(());
+        }
+        let (offset, token) = self.next(&format!("'{expected}'"))?;
+        Err(SelectorError {
+            offset,
+            message: format!("Expected '{expected}' instead of {token}"),
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Selector, SelectorError> {
+        let mut selectors = vec![self.parse_and()?];
+        while self.accept("or") {
+            selectors.push(self.parse_and()?);
+        }
+        Ok(match selectors.len() {
+            1 => selectors.remove(0),
+            _ => Selector::Or(selectors),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Selector, SelectorError> {
+        let mut selectors = vec![self.parse_unary()?];
+        while self.accept("and") {
+            selectors.push(self.parse_unary()?);
+        }
+        Ok(match selectors.len() {
+            1 => selectors.remove(0),
+            _ => Selector::And(selectors),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Selector, SelectorError> {
+        if self.accept("not") {
+            return Ok(Selector::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.accept("(") {
+            let selector = self.parse_or()?;
+            self.expect(")")?;
+            return Ok(selector);
+        }
+        if self.accept("exists") {
+            let path = self.parse_path()?;
+            return Ok(Selector::Exists(path));
+        }
+
+        let (offset, token) = self.next("a condition")?;
+        let Token::Word(word) = token else {
+            return Err(SelectorError {
+                offset,
+                message: format!("Expected a condition instead of {token}"),
+            });
+        };
+        match word.as_str() {
+            "template" => {
+                if self.accept("in") {
+                    return Ok(Selector::Template(self.parse_list()?));
+                }
+                let comparison = self.parse_equality()?;
+                let name = self.parse_text()?;
+                Ok(negate_unless_equal(
+                    Selector::Template(vec![name]),
+                    comparison,
+                ))
+            }
+            "source" => {
+                let comparison = self.parse_equality()?;
+                let name = self.parse_text()?;
+                Ok(negate_unless_equal(Selector::Source(name), comparison))
+            }
+            "region" => {
+                self.expect("contains")?;
+                let region = self.parse_text()?;
+                Ok(Selector::Contains(
+                    REGION_PATH.iter().map(|name| name.to_string()).collect(),
+                    region,
+                ))
+            }
+            _ => {
+                let path = to_path(offset, &word)?;
+                if self.accept("contains") {
+                    return Ok(Selector::Contains(path, self.parse_text()?));
+                }
+                let (comparison_offset, comparison) = self.parse_comparison()?;
+                let (operand_offset, operand) = self.next("a number or a text")?;
+                match (operand, comparison) {
+                    (Token::Number(number), _) => {
+                        Ok(Selector::Compare(path, comparison, Operand::Number(number)))
+                    }
+                    (Token::Text(text), Comparison::Equal | Comparison::NotEqual) => {
+                        Ok(Selector::Compare(path, comparison, Operand::Text(text)))
+                    }
+                    (Token::Text(_), _) => Err(SelectorError {
+                        offset: comparison_offset,
+                        message: "Only numbers can be compared with '<' and '>'".to_string(),
+                    }),
+                    (token, _) => Err(SelectorError {
+                        offset: operand_offset,
+                        message: format!("Expected a number or a text instead of {token}"),
+                    }),
+                }
+            }
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<(usize, Comparison), SelectorError> {
+        let (offset, token) = self.next("a comparison")?;
+        let comparison = match token {
+            Token::Symbol("=") => Comparison::Equal,
+            Token::Symbol("!=") => Comparison::NotEqual,
+            Token::Symbol("<") => Comparison::Less,
+            Token::Symbol("<=") => Comparison::LessOrEqual,
+            Token::Symbol(">") => Comparison::Greater,
+            Token::Symbol(">=") => Comparison::GreaterOrEqual,
+            token => {
+                return Err(SelectorError {
+                    offset,
+                    message: format!(
+                        "Expected a comparison, 'contains' or 'exists' instead of {token}"
+                    ),
+                })
+            }
+        };
+        Ok((offset, comparison))
+    }
+
+    fn parse_equality(&mut self) -> Result<Comparison, SelectorError> {
+        match self.parse_comparison()? {
+            (_, comparison @ (Comparison::Equal | Comparison::NotEqual)) => Ok(comparison),
+            (offset, _) => Err(SelectorError {
+                offset,
+                message: "Expected '=' or '!='".to_string(),
+            }),
+        }
+    }
+
+    fn parse_text(&mut self) -> Result<String, SelectorError> {
+        match self.next("a text in quotes")? {
+            (_, Token::Text(text)) => Ok(text),
+            (offset, token) => Err(SelectorError {
+                offset,
+                message: format!("Expected a text in quotes instead of {token}"),
+            }),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<String>, SelectorError> {
+        self.expect("[")?;
+        let mut items = vec![self.parse_text()?];
+        while self.accept(",") {
+            items.push(self.parse_text()?);
+        }
+        self.expect("]")?;
+        Ok(items)
+    }
+
+    fn parse_path(&mut self) -> Result<Vec<String>, SelectorError> {
+        match self.next("a path")? {
+            (offset, Token::Word(word)) => to_path(offset, &word),
+            (offset, token) => Err(SelectorError {
+                offset,
+                message: format!("Expected a path instead of {token}"),
+            }),
+        }
+    }
+}
+
+fn to_path(offset: usize, word: &str) -> Result<Vec<String>, SelectorError> {
+    let path = word.split('/').map(str::to_string).collect::<Vec<_>>();
+    match path.iter().all(|name| !name.is_empty()) {
+        true => Ok(path),
+        false => Err(SelectorError {
+            offset,
+            message: format!("'{word}' is not a valid path"),
+        }),
+    }
+}
+
+fn negate_unless_equal(selector: Selector, comparison: Comparison) -> Selector {
+    match comparison {
+        Comparison::NotEqual => Selector::Not(Box::new(selector)),
+        _ => selector,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::NodeType;
+
+    fn path(text: &str) -> Vec<String> {
+        text.split('/').map(str::to_string).collect()
+    }
+
+    fn error(text: &str) -> (usize, String) {
+        let error = Selector::parse(text).unwrap_err();
+        (error.offset, error.message)
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let number = |value: i64| Operand::Number(Decimal::from(value));
+        let cases = [
+            (
+                r#"template in ["A", 'B']"#,
+                Selector::Template(vec!["A".to_string(), "B".to_string()]),
+            ),
+            ("template = 'A'", Selector::Template(vec!["A".to_string()])),
+            (
+                "template != 'A'",
+                Selector::Not(Box::new(Selector::Template(vec!["A".to_string()]))),
+            ),
+            (
+                "region contains 'Arctic'",
+                Selector::Contains(path("Building/AssociatedRegions"), "Arctic".to_string()),
+            ),
+            ("source = \"dlc03\"", Selector::Source("dlc03".to_string())),
+            ("exists Electric", Selector::Exists(path("Electric"))),
+            (
+                "Standard/IconFilename = 'a b.png'",
+                Selector::Compare(
+                    path("Standard/IconFilename"),
+                    Comparison::Equal,
+                    Operand::Text("a b.png".to_string()),
+                ),
+            ),
+            (
+                "FactoryBase/CycleTime != 'x'",
+                Selector::Compare(
+                    path("FactoryBase/CycleTime"),
+                    Comparison::NotEqual,
+                    Operand::Text("x".to_string()),
+                ),
+            ),
+            (
+                "Cost/Amount <= -1.5",
+                Selector::Compare(
+                    path("Cost/Amount"),
+                    Comparison::LessOrEqual,
+                    Operand::Number(Decimal::new(-15, 1)),
+                ),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Selector::parse(text).unwrap(), expected, "{text}");
+        }
+        for (text, comparison) in [
+            ("=", Comparison::Equal),
+            ("!=", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            ("<=", Comparison::LessOrEqual),
+            (">", Comparison::Greater),
+            (">=", Comparison::GreaterOrEqual),
+        ] {
+            assert_eq!(
+                Selector::parse(&format!("A/B {text} 1")).unwrap(),
+                Selector::Compare(path("A/B"), comparison, number(1)),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn and_binds_closer_than_or() {
+        let a = || Selector::Exists(path("A"));
+        let b = || Selector::Exists(path("B"));
+        let c = || Selector::Exists(path("C"));
+        assert_eq!(
+            Selector::parse("exists A or exists B and exists C").unwrap(),
+            Selector::Or(vec![a(), Selector::And(vec![b(), c()])])
+        );
+        assert_eq!(
+            Selector::parse("(exists A or exists B) and not exists C").unwrap(),
+            Selector::And(vec![
+                Selector::Or(vec![a(), b()]),
+                Selector::Not(Box::new(c()))
+            ])
+        );
+        assert_eq!(
+            Selector::parse("exists A and exists B and exists C").unwrap(),
+            Selector::And(vec![a(), b(), c()])
+        );
+        assert_eq!(
+            Selector::parse("not not exists A").unwrap(),
+            Selector::Not(Box::new(Selector::Not(Box::new(a()))))
+        );
+    }
+
+    #[test]
+    fn reports_malformed_selectors_at_their_offset() {
+        let cases = [
+            ("", 0, "Expected a condition at the end"),
+            ("exists", 6, "Expected a path at the end"),
+            (
+                "exists A exists B",
+                9,
+                "Expected 'and' or 'or' instead of 'exists'",
+            ),
+            ("template = 'A", 11, "Text is not closed"),
+            ("template < 'A'", 9, "Expected '=' or '!='"),
+            ("template in 'A'", 12, "Expected '[' instead of \"A\""),
+            ("template in ['A' 'B']", 17, "Expected ']' instead of \"B\""),
+            (
+                "template = A",
+                11,
+                "Expected a text in quotes instead of 'A'",
+            ),
+            ("region = 'A'", 7, "Expected 'contains' instead of '='"),
+            (
+                "A/B > 'x'",
+                4,
+                "Only numbers can be compared with '<' and '>'",
+            ),
+            ("A/B = C", 6, "Expected a number or a text instead of 'C'"),
+            (
+                "A/B 1",
+                4,
+                "Expected a comparison, 'contains' or 'exists' instead of 1",
+            ),
+            ("A//B = 1", 0, "'A//B' is not a valid path"),
+            ("A = 1.2.3", 4, "'1.2.3' is not a number"),
+            ("(exists A", 9, "Expected ')' at the end"),
+            ("exists A or ", 12, "Expected a condition at the end"),
+            ("A = 1 & B = 2", 6, "Unexpected '&'"),
+            ("= 1", 0, "Expected a condition instead of '='"),
+        ];
+        for (text, offset, message) in cases {
+            assert_eq!(error(text), (offset, message.to_string()), "{text}");
+        }
+    }
+
+    const PROPERTIES: &str = r#"<Properties><DefaultValues>
+  <Standard><GUID>0</GUID><IconFilename>default.png</IconFilename></Standard>
+  <Building><AssociatedRegions>Moderate</AssociatedRegions></Building>
+  <FactoryBase><CycleTime>30</CycleTime></FactoryBase>
+  <Electric><MaxElectricity>1</MaxElectricity></Electric>
+</DefaultValues></Properties>"#;
+
+    const TEMPLATES: &str = r#"<Templates>
+  <Template><Name>FactoryBuilding7</Name><Properties>
+    <Standard /><Building /><FactoryBase><CycleTime>60</CycleTime></FactoryBase>
+  </Properties></Template>
+  <Template><Name>Heater_Arctic</Name><Properties>
+    <Standard /><Building><AssociatedRegions>Arctic</AssociatedRegions></Building><Electric />
+  </Properties></Template>
+</Templates>"#;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>1</GUID><IconFilename>a.png</IconFilename></Standard>
+    <Building><AssociatedRegions>Moderate;Arctic</AssociatedRegions></Building>
+    <FactoryBase><CycleTime>15</CycleTime></FactoryBase>
+  </Values></Asset>
+  <Asset><Template>FactoryBuilding7</Template><Values>
+    <Standard><GUID>2</GUID></Standard>
+  </Values></Asset>
+  <Asset><BaseAssetGUID>1</BaseAssetGUID><Values>
+    <Standard><GUID>3</GUID></Standard>
+    <FactoryBase><CycleTime>120.5</CycleTime></FactoryBase>
+  </Values></Asset>
+</AssetList>"#;
+
+    const DLC_ASSETS: &str = r#"<AssetList>
+  <Asset><Template>Heater_Arctic</Template><Values>
+    <Standard><GUID>4</GUID></Standard>
+  </Values></Asset>
+</AssetList>"#;
+
+    fn database() -> AssetDatabase {
+        let dlc_assets = (
+            "maindata/data/dlc03/config/export/main/asset/assets.xml",
+            DLC_ASSETS,
+        );
+        AssetDatabase::from_documents(PROPERTIES, TEMPLATES, ASSETS, &[dlc_assets])
+    }
+
+    /// The GUIDs of the assets matching the selector.
+    fn matching(database: &AssetDatabase, text: &str) -> Vec<String> {
+        let selector = Selector::parse(text).unwrap();
+        (0..database.entries.len())
+            .filter(|&index| database.entries[index].node_type == NodeType::Asset)
+            .filter(|&index| selector.matches(database, index))
+            .map(|index| database.entries[index].identifier.value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_values_the_assets_end_up_with() {
+        let database = database();
+        let cases = [
+            ("template = 'FactoryBuilding7'", vec!["1", "2", "3"]),
+            ("template in ['Heater_Arctic', 'Mall']", vec!["4"]),
+            ("template != 'FactoryBuilding7'", vec!["4"]),
+            ("region contains 'Arctic'", vec!["1", "3", "4"]),
+            ("region contains 'Moderate'", vec!["1", "2", "3"]),
+            ("source = 'base'", vec!["1", "2", "3"]),
+            ("source = 'dlc03'", vec!["4"]),
+            ("FactoryBase/CycleTime > 15", vec!["2", "3"]),
+            ("FactoryBase/CycleTime >= 120.5", vec!["3"]),
+            ("FactoryBase/CycleTime = 60", vec!["2"]),
+            ("FactoryBase/CycleTime != 60", vec!["1", "3"]),
+            (
+                "FactoryBase/CycleTime < 100 and not FactoryBase/CycleTime < 20",
+                vec!["2"],
+            ),
+            ("Standard/IconFilename = 'a.png'", vec!["1", "3"]),
+            ("Standard/IconFilename = 'default.png'", vec!["2", "4"]),
+            ("Standard/IconFilename != 'a.png'", vec!["2", "4"]),
+            ("exists FactoryBase/CycleTime", vec!["1", "2", "3"]),
+            ("exists Electric/MaxElectricity", vec!["4"]),
+            ("exists Standard/Name", vec![]),
+            ("FactoryBase/CycleTime = 'x' or exists Nothing", vec![]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(matching(&database, text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn exists_ignores_default_values_the_template_does_not_have() {
+        let database = database();
+        assert_eq!(matching(&database, "exists Electric"), ["4"]);
+        assert_eq!(matching(&database, "not exists Electric"), ["1", "2", "3"]);
+        assert_eq!(matching(&database, "Electric/MaxElectricity = 1"), ["4"]);
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Where a file of the game data comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) enum Source {
+    /// The base game, which is always installed.
+    BaseGame,
+    /// A DLC folder of the base game, like `data/dlc01`.
+    Dlc(String),
+    /// Another mod, identified by its ModID.
+    Mod(String),
+}
+
+impl Source {
+    /// Whether the source can be missing when the generated mods are used.
+    pub(crate) fn is_optional(&self) -> bool {
+        *self != Source::BaseGame
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::BaseGame => write!(f, "base game"),
+            Source::Dlc(dlc) => write!(f, "DLC {dlc}"),
+            Source::Mod(mod_id) => write!(f, "mod {mod_id}"),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum State {
     Included,
     Excluded,
@@ -6,3 +8,48 @@ pub(crate) enum State {
     Forced,
     ForcedByAncestor,
 }
+
+/// The rule of the recipe that decided the state of an entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Reason {
+    /// The GUID is listed in `forced_guids`.
+    ForcedGuid,
+    /// The GUID is listed in `excluded_guids`.
+    ExcludedGuid,
+    /// The template is listed in `excluded_templates`.
+    ExcludedTemplate,
+    /// The asset matches `forced_where`.
+    ForcedSelector,
+    /// The asset matches `excluded_where`.
+    ExcludedSelector,
+    /// The state follows from the state of the entry it inherits from.
+    Inherited,
+    /// Nothing of the recipe mentions the DefaultValues or template, so it is included.
+    NotListed,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Included => write!(f, "included"),
+            State::Excluded => write!(f, "excluded"),
+            State::ExcludedByAncestor => write!(f, "excluded by an ancestor"),
+            State::Forced => write!(f, "forced"),
+            State::ForcedByAncestor => write!(f, "forced by an ancestor"),
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::ForcedGuid => write!(f, "its GUID is listed in forced_guids"),
+            Reason::ExcludedGuid => write!(f, "its GUID is listed in excluded_guids"),
+            Reason::ExcludedTemplate => write!(f, "it is listed in excluded_templates"),
+            Reason::ForcedSelector => write!(f, "it matches forced_where"),
+            Reason::ExcludedSelector => write!(f, "it matches excluded_where"),
+            Reason::Inherited => write!(f, "it follows the entry it inherits from"),
+            Reason::NotListed => write!(f, "the recipe does not list it"),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{error::Error, layer::DataFile};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// The name shown in the game for each GUID, read from the `texts_<language>.xml` files in load
+/// order so a later file replaces the names of an earlier one.
+///
+/// Mods usually add their texts with ModOps, the `<Text>` nodes they add are read as well.
+pub(crate) fn load_texts(files: &[DataFile]) -> (HashMap<String, String>, Vec<Error>) {
+    let results = files
+        .par_iter()
+        .map(|file| {
+            let xml_string = file.read_to_string()?;
+            let xml = roxmltree::Document::parse(&xml_string).map_err(|source| Error::Xml {
+                path: file.path.clone(),
+                source,
+            })?;
+            Ok(xml
+                .descendants()
+                .filter(|node| node.has_tag_name("Text"))
+                .filter_map(|node| Some((child_text(&node, "GUID")?, child_text(&node, "Text")?)))
+                .collect::<Vec<_>>())
+        })
+        .collect::<Vec<Result<_, Error>>>();
+
+    let mut texts = HashMap::new();
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(file_texts) => texts.extend(file_texts),
+            Err(error) => errors.push(error),
+        }
+    }
+    (texts, errors)
+}
+
+fn child_text(node: &roxmltree::Node, name: &str) -> Option<String> {
+    let text = node
+        .children()
+        .find(|child| child.has_tag_name(name))?
+        .text()?
+        .trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
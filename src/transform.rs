@@ -0,0 +1,151 @@
+use crate::value::Value;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+/// A single step of the value computation attached to a query leaf.
+///
+/// In the recipe file a transform is either a table with one key, `{ multiply = 30 }`,
+/// or for the rounding steps just the name, `"ceil"`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum Transform {
+    Set(Decimal),
+    Multiply(Decimal),
+    Divide(Decimal),
+    Add(Decimal),
+    /// Raises the value to at least the given bound.
+    Min(Decimal),
+    /// Lowers the value to at most the given bound.
+    Max(Decimal),
+    /// Multiplies the value by the given percentage, `150` makes it one and a half times as big.
+    Percent(Decimal),
+    Round,
+    Ceil,
+    Floor,
+}
+
+impl Transform {
+    fn apply(&self, value: Decimal) -> Option<Decimal> {
+        match self {
+            Transform::Set(x) => Some(*x),
+            Transform::Multiply(x) => value.checked_mul(*x),
+            Transform::Divide(x) => value.checked_div(*x),
+            Transform::Add(x) => value.checked_add(*x),
+            Transform::Min(x) => Some(value.max(*x)),
+            Transform::Max(x) => Some(value.min(*x)),
+            Transform::Percent(x) => value.checked_mul(*x / Decimal::ONE_HUNDRED),
+            Transform::Round => {
+                Some(value.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero))
+            }
+            Transform::Ceil => Some(value.ceil()),
+            Transform::Floor => Some(value.floor()),
+        }
+    }
+
+    /// Returns a description of the problem if the transform can never be applied.
+    pub(crate) fn validate(&self) -> Option<String> {
+        match self {
+            Transform::Divide(x) if x.is_zero() => Some("Division by zero".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Applies the transforms in order to the current value of a leaf.
+///
+/// The result keeps the style of the current value, see [`Value::with_number`].
+pub(crate) fn apply(transforms: &[Transform], current_value: &Value) -> Result<Value, String> {
+    let is_set_only = transforms
+        .iter()
+        .all(|transform| matches!(transform, Transform::Set(_)));
+    let number = match (current_value.as_decimal(), current_value) {
+        (Some(number), _) => number,
+        (None, Value::Bool(_)) if is_set_only => Decimal::ZERO,
+        (None, Value::Bool(_)) => {
+            return Err(format!(
+                "'{current_value}' is a boolean, only set can be used on it"
+            ))
+        }
+        (None, _) => return Err(format!("'{current_value}' is not a number")),
+    };
+
+    let number = transforms
+        .iter()
+        .try_fold(number, |number, transform| transform.apply(number))
+        .ok_or_else(|| format!("Transforming '{current_value}' overflows"))?;
+
+    current_value.with_number(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn applies_the_transforms_in_order() {
+        use Transform::*;
+        let cases = [
+            ("10", vec![Min(number("20"))], Ok("20")),
+            ("30", vec![Min(number("20"))], Ok("30")),
+            ("30", vec![Max(number("20"))], Ok("20")),
+            ("10", vec![Max(number("20"))], Ok("10")),
+            ("60", vec![Percent(number("150"))], Ok("90")),
+            ("0.50", vec![Percent(number("50"))], Ok("0.25")),
+            (
+                "10",
+                vec![Add(number("5")), Multiply(number("2"))],
+                Ok("30"),
+            ),
+            (
+                "10",
+                vec![Multiply(number("2")), Add(number("5"))],
+                Ok("25"),
+            ),
+            ("2.5", vec![Round], Ok("3.0")),
+            ("-2.5", vec![Round], Ok("-3.0")),
+            ("2.4", vec![Round], Ok("2.0")),
+            ("10", vec![Divide(number("4")), Round], Ok("3")),
+            ("1.2", vec![Ceil], Ok("2.0")),
+            ("1.8", vec![Floor], Ok("1.0")),
+            ("7", vec![Set(number("0.5"))], Ok("0")),
+            ("true", vec![Set(number("0"))], Ok("false")),
+            ("false", vec![Set(number("1"))], Ok("true")),
+            (
+                "10",
+                vec![Multiply(Decimal::MAX)],
+                Err("Transforming '10' overflows"),
+            ),
+            (
+                "true",
+                vec![Multiply(number("2"))],
+                Err("'true' is a boolean, only set can be used on it"),
+            ),
+            (
+                "Name",
+                vec![Add(number("1"))],
+                Err("'Name' is not a number"),
+            ),
+        ];
+        for (text, transforms, expected) in cases {
+            assert_eq!(
+                apply(&transforms, &Value::parse(text)).map(|value| value.to_string()),
+                expected.map(str::to_string).map_err(str::to_string),
+                "{text} {transforms:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_division_by_zero() {
+        assert_eq!(
+            Transform::Divide(Decimal::ZERO).validate(),
+            Some("Division by zero".to_string())
+        );
+        assert_eq!(Transform::Divide(number("2")).validate(), None);
+        assert_eq!(Transform::Multiply(Decimal::ZERO).validate(), None);
+    }
+}
@@ -0,0 +1,195 @@
+use rust_decimal::Decimal;
+use std::{fmt, str::FromStr};
+
+/// The most decimals a computed fraction gets in addition to the ones of the original value.
+const MAX_DECIMALS: u32 = 6;
+
+/// The value of a leaf, typed by what its text in the game data looks like.
+///
+/// Writing a value back produces the same style as the original text, so an integer stays an
+/// integer and `0.50` keeps its two decimals.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Integer(i64),
+    Float { value: Decimal, decimals: u32 },
+    Bool(bool),
+    Text(String),
+}
+
+impl Value {
+    pub(crate) fn parse(text: &str) -> Value {
+        let trimmed = text.trim();
+
+        if let Ok(integer) = trimmed.parse::<i64>() {
+            return Value::Integer(integer);
+        }
+
+        if trimmed.contains(['.', 'e', 'E']) {
+            let parsed = match trimmed.contains(['e', 'E']) {
+                true => Decimal::from_scientific(trimmed),
+                false => Decimal::from_str(trimmed),
+            };
+            if let Ok(value) = parsed {
+                return Value::Float {
+                    value,
+                    decimals: value.scale(),
+                };
+            }
+        }
+
+        match trimmed {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::Text(text.to_string()),
+        }
+    }
+
+    /// The numeric value, if this is a number.
+    pub(crate) fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Integer(integer) => Some(Decimal::from(*integer)),
+            Value::Float { value, .. } => Some(*value),
+            Value::Bool(_) | Value::Text(_) => None,
+        }
+    }
+
+    /// Converts a computed number back into the style of this value.
+    ///
+    /// Integers cut off the fraction, fractions keep at least their original number of
+    /// decimals and get up to six more only if the rounded result needs them.
+    pub(crate) fn with_number(&self, number: Decimal) -> Result<Value, String> {
+        match self {
+            Value::Integer(_) => number
+                .trunc()
+                .try_into()
+                .map(Value::Integer)
+                .map_err(|_| format!("{number} does not fit into an integer")),
+            Value::Float { decimals, .. } => {
+                let mut value = number.round_dp(decimals + MAX_DECIMALS).normalize();
+                let decimals = value.scale().max(*decimals);
+                value.rescale(decimals);
+                Ok(Value::Float { value, decimals })
+            }
+            Value::Bool(_) => match number {
+                x if x.is_zero() => Ok(Value::Bool(false)),
+                x if x == Decimal::ONE => Ok(Value::Bool(true)),
+                x => Err(format!("{x} is not a boolean, use 0 or 1")),
+            },
+            Value::Text(text) => Err(format!("'{text}' is not a number")),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(integer) => write!(f, "{integer}"),
+            Value::Float { value, .. } => write!(f, "{value}"),
+            Value::Bool(boolean) => write!(f, "{boolean}"),
+            Value::Text(text) => write!(f, "{text}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text written for the number in the style of the original text.
+    fn write(original: &str, number: &str) -> String {
+        Value::parse(original)
+            .with_number(Decimal::from_str(number).unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn parses_the_type_from_the_text() {
+        assert_eq!(Value::parse(" 70 "), Value::Integer(70));
+        assert_eq!(Value::parse("-3"), Value::Integer(-3));
+        assert_eq!(
+            Value::parse("0.50"),
+            Value::Float {
+                value: Decimal::new(50, 2),
+                decimals: 2
+            }
+        );
+        assert_eq!(
+            Value::parse("1e-3"),
+            Value::Float {
+                value: Decimal::new(1, 3),
+                decimals: 3
+            }
+        );
+        assert_eq!(Value::parse("true"), Value::Bool(true));
+        assert_eq!(Value::parse("false"), Value::Bool(false));
+        assert_eq!(Value::parse("1.2.3"), Value::Text("1.2.3".to_string()));
+        assert_eq!(Value::parse(" Name "), Value::Text(" Name ".to_string()));
+    }
+
+    #[test]
+    fn writes_unchanged_values_like_the_original() {
+        for text in ["70", "-3", "0", "0.50", "-0.25", "1.000", "12.5", "0.0"] {
+            assert_eq!(Value::parse(text).to_string(), text);
+            assert_eq!(write(text, text), text);
+        }
+    }
+
+    #[test]
+    fn integers_cut_off_the_fraction() {
+        assert_eq!(write("70", "14"), "14");
+        assert_eq!(write("15", "3.75"), "3");
+        assert_eq!(write("15", "-3.75"), "-3");
+        assert_eq!(write("15", "-0.5"), "0");
+        assert_eq!(write("15", "2.000"), "2");
+        assert_eq!(
+            Value::Integer(0).with_number(Decimal::MAX),
+            Err(format!("{} does not fit into an integer", Decimal::MAX))
+        );
+    }
+
+    #[test]
+    fn fractions_keep_their_decimals_and_trailing_zeros() {
+        assert_eq!(write("0.50", "15"), "15.00");
+        assert_eq!(write("0.5", "3.0"), "3.0");
+        assert_eq!(write("1.000", "0.5"), "0.500");
+        assert_eq!(write("1.5", "1.25"), "1.25");
+        assert_eq!(write("0.1", "0.30000"), "0.3");
+        assert_eq!(write("-0.50", "-1.5"), "-1.50");
+        assert_eq!(write("0.5", "-2"), "-2.0");
+    }
+
+    #[test]
+    fn fractions_round_to_at_most_six_more_decimals() {
+        let third = Decimal::ONE / Decimal::from(3);
+        let with = |original: &str, number: Decimal| {
+            Value::parse(original)
+                .with_number(number)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(with("1.0", third), "0.3333333");
+        assert_eq!(with("1.0", -third), "-0.3333333");
+        assert_eq!(with("1.00", third * Decimal::TWO), "0.66666667");
+        assert_eq!(with("0.5", Decimal::new(123_456_789, 9)), "0.1234568");
+        assert_eq!(with("0.5", Decimal::new(1, 9)), "0.0");
+        assert_eq!(with("0.5", Decimal::new(-1, 9)), "0.0");
+        assert_eq!(with("0.5", Decimal::new(-5, 1) * Decimal::ZERO), "0.0");
+        assert_eq!(with("0.5", Decimal::new(10_000_004, 8)), "0.1");
+    }
+
+    #[test]
+    fn booleans_take_zero_or_one() {
+        let boolean = Value::Bool(true);
+        assert_eq!(boolean.with_number(Decimal::ZERO), Ok(Value::Bool(false)));
+        assert_eq!(boolean.with_number(Decimal::ONE), Ok(Value::Bool(true)));
+        assert_eq!(
+            boolean.with_number(Decimal::TWO),
+            Err("2 is not a boolean, use 0 or 1".to_string())
+        );
+        assert_eq!(
+            Value::parse("Name").with_number(Decimal::ONE),
+            Err("'Name' is not a number".to_string())
+        );
+    }
+}
@@ -1,13 +1,21 @@
+use crate::{element::Element, value::Value};
+
 #[derive(Debug, Clone)]
 pub(crate) struct XmlNode {
+    /// The name of the node, the XPath step like `Item[Product='1010017']` for a list item.
     pub(crate) name: String,
     pub(crate) present: bool,
     pub(crate) data: XmlNodeData,
+    /// The list a node holding queried items has, defined by the entry or inherited, so an entry
+    /// that has to set the items it inherits can get a copy of it.
+    pub(crate) list: Option<Element>,
 }
 
 #[derive(Debug, Clone)]
 pub(crate) enum XmlNodeData {
     Branch(Vec<XmlNode>),
-    Leaf(String),
+    Leaf(Value),
+    /// The inner XML of a node that is only handled as a whole.
+    Raw(String),
     None,
 }
@@ -1,11 +1,183 @@
-#[derive(Clone)]
+use crate::transform::Transform;
+
+#[derive(Debug, Clone)]
 pub(crate) struct XmlTag {
     pub(crate) name: String,
+    /// Makes the node stand for the items of a list, `Item[Product=1010017]`, instead of a single
+    /// child.
+    pub(crate) key: Option<ItemKey>,
     pub(crate) content: Content,
+    /// Where the node goes when it has to be added, the end of its parent if not set.
+    pub(crate) placement: Option<Placement>,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub(crate) enum Content {
     Branch(Vec<XmlTag>),
-    Leaf,
+    Leaf(Vec<Transform>),
+    /// The node is removed as a whole, whatever it contains.
+    Remove,
+}
+
+/// The sibling an added node is placed next to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Placement {
+    After(String),
+    Before(String),
+}
+
+/// Selects the items of a list by the value of one of their children.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ItemKey {
+    pub(crate) child: String,
+    /// Every item having the child is selected if not set.
+    pub(crate) value: Option<String>,
+}
+
+impl XmlTag {
+    /// The node as written in a recipe, `Item[Product=*]` for every item of a list.
+    pub(crate) fn step(&self) -> String {
+        match &self.key {
+            None => self.name.clone(),
+            Some(key) => format!(
+                "{}[{}={}]",
+                self.name,
+                key.child,
+                key.value.as_deref().unwrap_or("*")
+            ),
+        }
+    }
+
+    /// The XPath step of the item whose key child has the value, the name of the item in the
+    /// content and the ModOps.
+    pub(crate) fn item_step(&self, value: &str) -> String {
+        let child = self.key.as_ref().map_or("", |key| key.child.as_str());
+        match value.contains('\'') {
+            true => format!("{}[{child}=\"{value}\"]", self.name),
+            false => format!("{}[{child}='{value}']", self.name),
+        }
+    }
+
+    /// Whether the node of the content with the name was created for this node of the query.
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        let Some((step_name, step_key)) = parse_step(name) else {
+            return false;
+        };
+        step_name == self.name
+            && match (&self.key, step_key) {
+                (None, None) => true,
+                (Some(key), Some((child, value))) => {
+                    key.child == child && key.value.as_deref().is_none_or(|wanted| wanted == value)
+                }
+                _ => false,
+            }
+    }
+
+    /// Whether the two nodes can select the same child, so they can not both be in a branch.
+    pub(crate) fn overlaps(&self, other: &XmlTag) -> bool {
+        self.name == other.name
+            && match (&self.key, &other.key) {
+                (Some(key), Some(other_key)) => {
+                    key.child != other_key.child
+                        || key.value.is_none()
+                        || other_key.value.is_none()
+                        || key.value == other_key.value
+                }
+                _ => true,
+            }
+    }
+}
+
+/// Splits a step of a path into the name and, for list items, the key child and its value.
+/// `Item[Product=1010017]` and `Item[Product='1010017']` are read alike, `None` if the brackets
+/// are not closed or hold no `=`.
+pub(crate) fn parse_step(step: &str) -> Option<(&str, Option<(&str, &str)>)> {
+    let Some((name, key)) = step.split_once('[') else {
+        return Some((step, None));
+    };
+    let (child, value) = key.strip_suffix(']')?.split_once('=')?;
+    let value = value.trim();
+    let value = ['\'', '"']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value);
+    Some((name, Some((child.trim(), value))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, key: Option<(&str, Option<&str>)>) -> XmlTag {
+        XmlTag {
+            name: name.to_string(),
+            key: key.map(|(child, value)| ItemKey {
+                child: child.to_string(),
+                value: value.map(str::to_string),
+            }),
+            content: Content::Remove,
+            placement: None,
+        }
+    }
+
+    #[test]
+    fn writes_the_steps_of_items() {
+        let item = tag("Item", Some(("Product", Some("1010017"))));
+        assert_eq!(item.step(), "Item[Product=1010017]");
+        assert_eq!(item.item_step("1010017"), "Item[Product='1010017']");
+        assert_eq!(item.item_step("it's"), "Item[Product=\"it's\"]");
+        assert_eq!(
+            tag("Item", Some(("Product", None))).step(),
+            "Item[Product=*]"
+        );
+        assert_eq!(tag("Amount", None).step(), "Amount");
+    }
+
+    #[test]
+    fn matches_the_content_created_for_the_node() {
+        let any = tag("Item", Some(("Product", None)));
+        let one = tag("Item", Some(("Product", Some("1010017"))));
+        assert!(any.matches("Item[Product='1010017']"));
+        assert!(any.matches("Item[Product=\"1010018\"]"));
+        assert!(!any.matches("Item[Guid='1010017']"));
+        assert!(!any.matches("Item"));
+        assert!(one.matches("Item[Product='1010017']"));
+        assert!(!one.matches("Item[Product='1010018']"));
+        assert!(tag("Item", None).matches("Item"));
+        assert!(!tag("Item", None).matches("Item[Product='1010017']"));
+    }
+
+    #[test]
+    fn finds_nodes_selecting_the_same_items() {
+        let one = tag("Item", Some(("Product", Some("1"))));
+        let two = tag("Item", Some(("Product", Some("2"))));
+        let any = tag("Item", Some(("Product", None)));
+        let other_key = tag("Item", Some(("Guid", Some("2"))));
+        assert!(!one.overlaps(&two));
+        assert!(one.overlaps(&one));
+        assert!(one.overlaps(&any));
+        assert!(any.overlaps(&two));
+        assert!(one.overlaps(&other_key));
+        assert!(one.overlaps(&tag("Item", None)));
+        assert!(!one.overlaps(&tag("Entry", Some(("Product", Some("1"))))));
+    }
+
+    #[test]
+    fn splits_steps_into_name_and_key() {
+        assert_eq!(parse_step("Item"), Some(("Item", None)));
+        assert_eq!(
+            parse_step("Item[Product=1]"),
+            Some(("Item", Some(("Product", "1"))))
+        );
+        assert_eq!(
+            parse_step("Item[ Product = '1' ]"),
+            Some(("Item", Some(("Product", "1"))))
+        );
+        assert_eq!(
+            parse_step("Item[Product=\"1\"]"),
+            Some(("Item", Some(("Product", "1"))))
+        );
+        assert_eq!(parse_step("Item[Product=1"), None);
+        assert_eq!(parse_step("Item[Product]"), None);
+    }
 }
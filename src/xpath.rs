@@ -0,0 +1,767 @@
+use crate::element::Element;
+use std::collections::HashSet;
+
+/// The subset of XPath used by the ModOps of mods: child and descendant steps, `*`, `.` and `..`,
+/// `text()`, and predicates with positions, `last()`, paths, `=`, `!=`, `and`, `or` and `not()`.
+///
+/// Nodes are addressed by the indices of the children leading to them from the root element, so
+/// the document can be changed after selecting them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct XPath {
+    absolute: bool,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    descendant: bool,
+    test: Test,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Test {
+    Name(String),
+    Any,
+    SelfNode,
+    Parent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Exists(XPath),
+    Compare {
+        path: XPath,
+        value: String,
+        equal: bool,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+/// Where a path is evaluated, the document above the root element or an element.
+#[derive(Debug, Clone, PartialEq)]
+enum Context {
+    Document,
+    Node(Vec<usize>),
+}
+
+impl XPath {
+    pub(crate) fn parse(text: &str) -> Result<XPath, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Empty XPath".to_string());
+        }
+
+        let (absolute, rest) = match text.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        if rest.is_empty() {
+            return Ok(XPath {
+                absolute,
+                steps: Vec::new(),
+            });
+        }
+
+        let mut steps = Vec::new();
+        let mut descendant = absolute && rest.starts_with('/');
+        let rest = if descendant { &rest[1..] } else { rest };
+        if rest.is_empty() {
+            return Err(format!("'{text}' ends with '/'"));
+        }
+        for part in split_top_level(rest, '/')? {
+            if part.is_empty() {
+                if descendant {
+                    return Err(format!("Unexpected '///' in '{text}'"));
+                }
+                descendant = true;
+                continue;
+            }
+            steps.push(
+                parse_step(part, descendant).map_err(|error| format!("{error} in '{text}'"))?,
+            );
+            descendant = false;
+        }
+        if descendant {
+            return Err(format!("'{text}' ends with '/'"));
+        }
+
+        Ok(XPath { absolute, steps })
+    }
+
+    /// The matching elements in document order. Absolute paths start above the root element,
+    /// relative ones at `context`.
+    pub(crate) fn select(&self, root: &Element, context: &[usize]) -> Vec<Vec<usize>> {
+        let start = match self.absolute {
+            true => Context::Document,
+            false => Context::Node(context.to_vec()),
+        };
+        self.select_from(root, start)
+    }
+
+    /// Like `select`, but absolute paths start at `context` as well, like the `Path` of a ModOp
+    /// with a `GUID`.
+    pub(crate) fn select_below(&self, root: &Element, context: &[usize]) -> Vec<Vec<usize>> {
+        self.select_from(root, Context::Node(context.to_vec()))
+    }
+
+    /// The GUID of a path starting with `//Asset[Values/Standard/GUID='<GUID>']`, as most ModOps
+    /// do, with the rest of the path below the asset. The assets can then be looked up by their
+    /// GUID instead of searching the whole document.
+    pub(crate) fn asset_guid(&self) -> Option<(&str, XPath)> {
+        let (first, rest) = self.steps.split_first()?;
+        let guid_path = ["Values", "Standard", "GUID"];
+        match (self.absolute, first) {
+            (
+                true,
+                Step {
+                    descendant: true,
+                    test: Test::Name(name),
+                    predicates,
+                },
+            ) if name == "Asset" => match predicates.as_slice() {
+                [Predicate::Compare {
+                    path,
+                    value,
+                    equal: true,
+                }] if !path.absolute
+                    && path.steps.len() == guid_path.len()
+                    && path.steps.iter().zip(guid_path).all(|(step, name)| {
+                        !step.descendant
+                            && step.predicates.is_empty()
+                            && step.test == Test::Name(name.to_string())
+                    }) =>
+                {
+                    Some((
+                        value,
+                        XPath {
+                            absolute: false,
+                            steps: rest.to_vec(),
+                        },
+                    ))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether any element a relative path of plain child steps, like `Standard/GUID`, selects
+    /// below the element fulfills the condition. `None` for any other path, which needs `select`.
+    fn any_below(&self, element: &Element, condition: &impl Fn(&Element) -> bool) -> Option<bool> {
+        let is_plain = !self.absolute
+            && self.steps.iter().all(|step| {
+                !step.descendant
+                    && step.predicates.is_empty()
+                    && matches!(step.test, Test::Name(_) | Test::Any)
+            });
+        is_plain.then(|| any_below(&self.steps, element, condition))
+    }
+
+    fn select_from(&self, root: &Element, start: Context) -> Vec<Vec<usize>> {
+        let mut contexts = vec![start];
+        for step in &self.steps {
+            let mut seen = HashSet::new();
+            contexts = contexts
+                .iter()
+                .flat_map(|context| step.apply(root, context))
+                .filter(|path| seen.insert(path.clone()))
+                .map(Context::Node)
+                .collect();
+        }
+
+        let mut paths = contexts
+            .into_iter()
+            .filter_map(|context| match context {
+                Context::Node(path) => Some(path),
+                Context::Document => None,
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+}
+
+impl Step {
+    fn apply(&self, root: &Element, context: &Context) -> Vec<Vec<usize>> {
+        let mut result = Vec::new();
+        let mut add = |base: Option<(&[usize], &Element)>| {
+            let candidates = self.candidates(root, base);
+            let candidates = self
+                .predicates
+                .iter()
+                .fold(candidates, |candidates, predicate| {
+                    let count = candidates.len();
+                    candidates
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(index, (path, element))| {
+                            predicate.matches(root, path, element, index + 1, count)
+                        })
+                        .map(|(_, candidate)| candidate)
+                        .collect()
+                });
+            result.extend(candidates.into_iter().map(|(path, _)| path));
+        };
+
+        // The descendants are visited in document order without collecting them first, `//` is
+        // in most ModOps and the documents have millions of elements.
+        match (self.descendant, context) {
+            (false, Context::Document) => add(None),
+            (false, Context::Node(path)) => add(Some((path, get(root, path)))),
+            (true, Context::Document) => {
+                add(None);
+                walk(root, &mut Vec::new(), &mut |path, element| {
+                    add(Some((path, element)))
+                });
+            }
+            (true, Context::Node(path)) => {
+                walk(get(root, path), &mut path.clone(), &mut |path, element| {
+                    add(Some((path, element)))
+                })
+            }
+        }
+        result
+    }
+
+    /// The elements the test selects from the base, the document above the root element if
+    /// `None`.
+    fn candidates<'a>(
+        &self,
+        root: &'a Element,
+        base: Option<(&[usize], &'a Element)>,
+    ) -> Vec<(Vec<usize>, &'a Element)> {
+        match (&self.test, base) {
+            (Test::SelfNode, Some((path, element))) => vec![(path.to_vec(), element)],
+            (Test::Parent, Some((path, _))) if !path.is_empty() => {
+                let parent_path = &path[..path.len() - 1];
+                vec![(parent_path.to_vec(), get(root, parent_path))]
+            }
+            (Test::SelfNode | Test::Parent, _) => Vec::new(),
+            (Test::Name(_) | Test::Any, None) => match self.test.matches(root) {
+                true => vec![(Vec::new(), root)],
+                false => Vec::new(),
+            },
+            (Test::Name(_) | Test::Any, Some((path, element))) => element
+                .children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| self.test.matches(child))
+                .map(|(index, child)| ([path, &[index]].concat(), child))
+                .collect(),
+        }
+    }
+}
+
+fn any_below(steps: &[Step], element: &Element, condition: &impl Fn(&Element) -> bool) -> bool {
+    match steps.split_first() {
+        Some((step, rest)) => element
+            .children
+            .iter()
+            .filter(|child| step.test.matches(child))
+            .any(|child| any_below(rest, child, condition)),
+        None => condition(element),
+    }
+}
+
+/// Calls `visit` with the element and every element below it, in document order.
+fn walk(element: &Element, path: &mut Vec<usize>, visit: &mut impl FnMut(&[usize], &Element)) {
+    visit(path, element);
+    element
+        .children
+        .iter()
+        .enumerate()
+        .for_each(|(index, child)| {
+            path.push(index);
+            walk(child, path, visit);
+            path.pop();
+        });
+}
+
+impl Test {
+    fn matches(&self, element: &Element) -> bool {
+        match self {
+            Test::Name(name) => element.name == *name,
+            Test::Any => true,
+            Test::SelfNode | Test::Parent => false,
+        }
+    }
+}
+
+impl Predicate {
+    fn matches(
+        &self,
+        root: &Element,
+        path: &[usize],
+        element: &Element,
+        position: usize,
+        count: usize,
+    ) -> bool {
+        match self {
+            Predicate::Position(expected) => position == *expected,
+            Predicate::Last => position == count,
+            Predicate::Exists(xpath) => match xpath.any_below(element, &|_| true) {
+                Some(matches) => matches,
+                None => !xpath.select(root, path).is_empty(),
+            },
+            Predicate::Compare {
+                path: xpath,
+                value,
+                equal,
+            } => {
+                let has_value =
+                    |element: &Element| element.text.as_deref().map(str::trim) == Some(value);
+                let matches = match xpath.any_below(element, &has_value) {
+                    Some(matches) => matches,
+                    None => xpath
+                        .select(root, path)
+                        .iter()
+                        .any(|path| has_value(get(root, path))),
+                };
+                matches == *equal
+            }
+            Predicate::And(left, right) => {
+                left.matches(root, path, element, position, count)
+                    && right.matches(root, path, element, position, count)
+            }
+            Predicate::Or(left, right) => {
+                left.matches(root, path, element, position, count)
+                    || right.matches(root, path, element, position, count)
+            }
+            Predicate::Not(inner) => !inner.matches(root, path, element, position, count),
+        }
+    }
+}
+
+/// The element at the given path below the root element.
+pub(crate) fn get<'a>(root: &'a Element, path: &[usize]) -> &'a Element {
+    path.iter()
+        .fold(root, |element, &index| &element.children[index])
+}
+
+pub(crate) fn get_mut<'a>(root: &'a mut Element, path: &[usize]) -> &'a mut Element {
+    path.iter()
+        .fold(root, |element, &index| &mut element.children[index])
+}
+
+fn parse_step(text: &str, descendant: bool) -> Result<Step, String> {
+    let (name, mut rest) = match text.find('[') {
+        Some(index) => (text[..index].trim(), &text[index..]),
+        None => (text.trim(), ""),
+    };
+    let test = match name {
+        "*" => Test::Any,
+        "." => Test::SelfNode,
+        ".." => Test::Parent,
+        "text()" => Test::SelfNode,
+        _ if name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+            && !name.is_empty() =>
+        {
+            Test::Name(name.to_string())
+        }
+        _ => return Err(format!("Unsupported step '{text}'")),
+    };
+
+    let mut predicates = Vec::new();
+    while !rest.is_empty() {
+        let end = closing_bracket(rest).ok_or_else(|| format!("Unclosed '[' in '{text}'"))?;
+        predicates.push(PredicateParser::new(&rest[1..end]).parse()?);
+        rest = rest[end + 1..].trim_start();
+        if !rest.is_empty() && !rest.starts_with('[') {
+            return Err(format!("Unexpected '{rest}' after predicate"));
+        }
+    }
+
+    Ok(Step {
+        descendant,
+        test,
+        predicates,
+    })
+}
+
+/// The index of the `]` closing the `[` the text starts with.
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Splits at the separator where it is not inside brackets, parentheses or quotes.
+fn split_top_level(text: &str, separator: char) -> Result<Vec<&str>, String> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, _) if c == separator && depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    if depth != 0 || quote.is_some() {
+        return Err(format!("Unbalanced brackets or quotes in '{text}'"));
+    }
+    parts.push(&text[start..]);
+    Ok(parts)
+}
+
+struct PredicateParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> PredicateParser<'a> {
+    fn new(text: &'a str) -> PredicateParser<'a> {
+        PredicateParser { text, position: 0 }
+    }
+
+    fn parse(mut self) -> Result<Predicate, String> {
+        let predicate = self.parse_or()?;
+        self.skip_whitespace();
+        match self.rest().is_empty() {
+            true => Ok(predicate),
+            false => Err(format!(
+                "Unexpected '{}' in predicate '{}'",
+                self.rest(),
+                self.text
+            )),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, String> {
+        let mut left = self.parse_and()?;
+        while self.eat_keyword("or") {
+            left = Predicate::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, String> {
+        let mut left = self.parse_unary()?;
+        while self.eat_keyword("and") {
+            left = Predicate::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Predicate, String> {
+        self.skip_whitespace();
+        if self.eat("not(") {
+            let inner = self.parse_or()?;
+            self.expect(')')?;
+            return Ok(Predicate::Not(Box::new(inner)));
+        }
+        if self.eat("last()") {
+            return Ok(Predicate::Last);
+        }
+        if self.eat("(") {
+            let inner = self.parse_or()?;
+            self.expect(')')?;
+            return Ok(inner);
+        }
+
+        let path_text = self.take_path();
+        if path_text.is_empty() {
+            return Err(format!("Expected a path in predicate '{}'", self.text));
+        }
+        if let Ok(position) = path_text.parse::<usize>() {
+            return Ok(Predicate::Position(position));
+        }
+        let path = XPath::parse(path_text)?;
+
+        self.skip_whitespace();
+        let equal = match (self.eat("!="), self.eat("=")) {
+            (true, _) => false,
+            (false, true) => true,
+            (false, false) => return Ok(Predicate::Exists(path)),
+        };
+        let value = self.take_literal()?;
+        Ok(Predicate::Compare { path, value, equal })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest().starts_with(token) {
+            true => {
+                self.position += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let rest = self.rest();
+        match rest.starts_with(keyword)
+            && rest[keyword.len()..].starts_with(|c: char| c.is_whitespace() || c == '(')
+        {
+            true => {
+                self.position += keyword.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.eat(&c.to_string()) {
+            true => Ok(()),
+            false => Err(format!("Expected '{c}' in predicate '{}'", self.text)),
+        }
+    }
+
+    /// Takes a path up to the next operator, whitespace or closing parenthesis.
+    fn take_path(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let mut depth = 0;
+        let mut quote = None;
+        let mut end = rest.len();
+        for (index, c) in rest.char_indices() {
+            match (quote, c) {
+                (Some(q), _) if c == q => quote = None,
+                (Some(_), _) => (),
+                (None, '\'' | '"') => quote = Some(c),
+                (None, '[') => depth += 1,
+                (None, ']') => depth -= 1,
+                (None, '(') if rest[..index].ends_with("text") => (),
+                (None, ')') if rest[..index].ends_with("text(") => (),
+                (None, '=' | '!' | ')') if depth == 0 => {
+                    end = index;
+                    break;
+                }
+                (None, _) if c.is_whitespace() && depth == 0 => {
+                    end = index;
+                    break;
+                }
+                _ => (),
+            }
+        }
+        self.position += end;
+        rest[..end].trim()
+    }
+
+    fn take_literal(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                let end = rest[1..]
+                    .find(quote)
+                    .ok_or_else(|| format!("Unclosed quote in predicate '{}'", self.text))?;
+                self.position += end + 2;
+                Ok(rest[1..end + 1].to_string())
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == ')')
+                    .unwrap_or(rest.len());
+                self.position += end;
+                match end {
+                    0 => Err(format!("Expected a value in predicate '{}'", self.text)),
+                    _ => Ok(rest[..end].to_string()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSETS: &str = r#"<AssetList>
+  <Groups>
+    <Group>
+      <Assets>
+        <Asset><Values><Standard><GUID>1</GUID><Name>A</Name></Standard></Values></Asset>
+        <Asset><Values><Standard><GUID>2</GUID></Standard><Cost /></Values></Asset>
+        <Asset><Values><Standard><GUID>3</GUID><Name>C</Name></Standard></Values></Asset>
+      </Assets>
+    </Group>
+  </Groups>
+</AssetList>"#;
+
+    fn root() -> Element {
+        Element::from_node(&roxmltree::Document::parse(ASSETS).unwrap().root_element())
+    }
+
+    /// The GUIDs of the assets the path selects, or the names of the elements if they are not
+    /// assets.
+    fn select(path: &str) -> Vec<String> {
+        let root = root();
+        XPath::parse(path)
+            .unwrap()
+            .select(&root, &[])
+            .iter()
+            .map(|path| {
+                let element = get(&root, path);
+                match element.name.as_str() {
+                    "Asset" => get(element, &[0, 0, 0]).text.clone().unwrap(),
+                    name => name.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_child_and_descendant_steps() {
+        assert_eq!(
+            select("/AssetList/Groups/Group/Assets/Asset"),
+            ["1", "2", "3"]
+        );
+        assert_eq!(select("//Asset"), ["1", "2", "3"]);
+        assert_eq!(select("/AssetList//Asset"), ["1", "2", "3"]);
+        assert_eq!(select("/AssetList/Groups//Assets/*"), ["1", "2", "3"]);
+        assert_eq!(select("//Cost/../.."), ["2"]);
+        assert_eq!(select("//Cost/."), ["Cost"]);
+        assert_eq!(select("/Groups"), Vec::<String>::new());
+        assert_eq!(select("/AssetList"), ["AssetList"]);
+    }
+
+    #[test]
+    fn descendant_steps_select_every_element_once_in_document_order() {
+        assert_eq!(
+            select("//Values//*"),
+            ["Standard", "GUID", "Name", "Standard", "GUID", "Cost", "Standard", "GUID", "Name"]
+        );
+        assert_eq!(select("//Group//Standard/GUID/.."), ["Standard"; 3]);
+    }
+
+    #[test]
+    fn predicates_filter_by_position_and_value() {
+        assert_eq!(select("//Asset[2]"), ["2"]);
+        assert_eq!(select("//Asset[last()]"), ["3"]);
+        assert_eq!(select("//Asset[Values/Standard/GUID='3']"), ["3"]);
+        assert_eq!(select("//Asset[Values/Standard/GUID = \"3\"]"), ["3"]);
+        assert_eq!(select("//Asset[Values/Standard/GUID=3]"), ["3"]);
+        assert_eq!(select("//Asset[Values/Standard/GUID!='3']"), ["1", "2"]);
+        assert_eq!(select("//Asset[Values/Cost]"), ["2"]);
+        assert_eq!(select("//Asset[not(Values/Cost)]"), ["1", "3"]);
+        assert_eq!(
+            select("//Asset[Values/Standard/Name='A' or Values/Standard/GUID='2']"),
+            ["1", "2"]
+        );
+        assert_eq!(
+            select("//Asset[Values/Standard/Name and (Values/Standard/GUID='1' or Values/Cost)]"),
+            ["1"]
+        );
+        assert_eq!(select("//Asset[Values/Standard/Name][2]"), ["3"]);
+        assert_eq!(select("//Standard[GUID/text()='2']/.."), ["Values"]);
+        assert_eq!(select("//GUID[.='2']"), ["GUID"]);
+    }
+
+    #[test]
+    fn predicates_with_other_paths_are_evaluated_at_the_element() {
+        assert_eq!(select("//Asset[.//Cost]"), ["2"]);
+        assert_eq!(select("//Name[../GUID='3']/../../.."), ["3"]);
+        assert_eq!(select("//Asset[*/Cost]"), ["2"]);
+        assert_eq!(select("//Asset[Values/*/Name='C']"), ["3"]);
+    }
+
+    #[test]
+    fn relative_paths_start_at_the_context() {
+        let root = root();
+        let asset = [0, 0, 0, 1];
+        let xpath = XPath::parse("Values/Standard/GUID").unwrap();
+        assert_eq!(xpath.select(&root, &asset), [vec![0, 0, 0, 1, 0, 0, 0]]);
+
+        let xpath = XPath::parse("/Values/Cost").unwrap();
+        assert!(xpath.select(&root, &asset).is_empty());
+        assert_eq!(xpath.select_below(&root, &asset), [vec![0, 0, 0, 1, 0, 1]]);
+    }
+
+    #[test]
+    fn recognizes_paths_starting_with_an_asset_guid() {
+        let xpath = XPath::parse("//Asset[Values/Standard/GUID='1010294']/Values/Cost").unwrap();
+        let (guid, rest) = xpath.asset_guid().unwrap();
+        assert_eq!(guid, "1010294");
+        assert_eq!(rest, XPath::parse("Values/Cost").unwrap());
+
+        for path in [
+            "/AssetList//Asset[Values/Standard/GUID='1']",
+            "//Asset[Values/Standard/GUID!='1']",
+            "//Asset[Values//GUID='1']",
+            "//Asset[Values/Standard/GUID='1'][1]",
+            "//Values[Standard/GUID='1']",
+        ] {
+            assert_eq!(XPath::parse(path).unwrap().asset_guid(), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for (path, message) in [
+            ("", "Empty XPath"),
+            ("//", "'//' ends with '/'"),
+            ("/a///b", "Unexpected '///' in '/a///b'"),
+            ("//Asset[1", "Unbalanced brackets or quotes in 'Asset[1'"),
+            (
+                "//Asset[GUID='1]",
+                "Unbalanced brackets or quotes in 'Asset[GUID='1]'",
+            ),
+            ("//a b", "Unsupported step 'a b' in '//a b'"),
+            (
+                "//Asset[]",
+                "Expected a path in predicate '' in '//Asset[]'",
+            ),
+            (
+                "//Asset[GUID=]",
+                "Expected a value in predicate 'GUID=' in '//Asset[GUID=]'",
+            ),
+            (
+                "//Asset[not(GUID]",
+                "Unbalanced brackets or quotes in 'Asset[not(GUID]'",
+            ),
+            (
+                "//Asset[GUID='1' GUID]",
+                "Unexpected 'GUID' in predicate 'GUID='1' GUID' in '//Asset[GUID='1' GUID]'",
+            ),
+            (
+                "//Asset[1]x",
+                "Unexpected 'x' after predicate in '//Asset[1]x'",
+            ),
+        ] {
+            assert_eq!(XPath::parse(path), Err(message.to_string()), "{path}");
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, path::Path, process::Command};
+
+const PROPERTIES: &str = r#"<Properties>
+  <DefaultValues>
+    <FactoryBase><CycleTime>30</CycleTime></FactoryBase>
+  </DefaultValues>
+</Properties>"#;
+
+const TEMPLATES: &str = r#"<Templates>
+  <Template>
+    <Name>FactoryBuilding7</Name>
+    <Properties><FactoryBase><CycleTime>60</CycleTime></FactoryBase></Properties>
+  </Template>
+</Templates>"#;
+
+const ASSETS: &str = r#"<AssetList>
+  <Asset>
+    <Template>FactoryBuilding7</Template>
+    <Values>
+      <Standard><GUID>1010294</GUID></Standard>
+      <FactoryBase><CycleTime>15</CycleTime></FactoryBase>
+    </Values>
+  </Asset>
+</AssetList>"#;
+
+const RECIPES: &str = r#"
+[[mod]]
+name = "Layout"
+query = { name = "FactoryBase", children = [{ name = "CycleTime", transform = [{ divide = 5 }] }] }
+"#;
+
+fn write(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+#[test]
+fn output_mirrors_the_game_data_directory_tree() {
+    let input = tempfile::tempdir().unwrap();
+    let output = tempfile::tempdir().unwrap();
+    let asset_directory = input.path().join("maindata/data/config/export/main/asset");
+    write(&asset_directory.join("properties.xml"), PROPERTIES);
+    write(&asset_directory.join("templates.xml"), TEMPLATES);
+    write(&asset_directory.join("assets.xml"), ASSETS);
+    let recipes = input.path().join("recipes.toml");
+    write(&recipes, RECIPES);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_anno_1800_mod_creator"))
+        .arg("generate")
+        .arg(input.path())
+        .arg(output.path())
+        .arg("--recipes")
+        .arg(&recipes)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mod_path = output.path().join("JJ's Enhanced Layout");
+    assert!(mod_path.join("modinfo.json").is_file());
+    for file_name in ["properties.xml", "templates.xml", "assets.xml"] {
+        let path = mod_path
+            .join("data")
+            .join("config")
+            .join("export")
+            .join("main")
+            .join("asset")
+            .join(file_name);
+        assert!(path.is_file(), "{} is missing", path.display());
+    }
+
+    let top_level = fs::read_dir(&mod_path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert!(
+        top_level.iter().all(|name| !name.contains('\\')),
+        "file names contain backslashes: {top_level:?}"
+    );
+}